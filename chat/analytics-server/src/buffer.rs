@@ -0,0 +1,283 @@
+use std::{sync::Arc, time::Duration};
+
+use clickhouse::Client;
+use tokio::{
+    sync::{mpsc, oneshot, Mutex},
+    task::JoinHandle,
+    time::Instant,
+};
+use tracing::{info, warn};
+
+use crate::{handler::AnalyticsEventRow, AppError, BatchConfig, EVENTS_TABLE};
+
+const CHANNEL_CAPACITY: usize = 1024;
+/// longest wait between retries of a failed write
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// writes tried on shutdown before the buffered rows are given up
+const SHUTDOWN_ATTEMPTS: u32 = 3;
+
+/// Buffers event rows and writes them to ClickHouse in batches, so each request
+/// doesn't create a part of its own. A batch is written once `max_rows` rows are
+/// buffered, or when the flush interval elapses. Failed writes are retried with
+/// backoff, up to `max_buffered_rows` rows are kept meanwhile.
+#[derive(Debug, Clone)]
+pub struct EventBuffer {
+    tx: mpsc::Sender<Vec<AnalyticsEventRow>>,
+    shutdown: Arc<Mutex<Option<Shutdown>>>,
+}
+
+/// Signals the task of the buffer to stop, and waits until it wrote the buffered rows.
+type Shutdown = (oneshot::Sender<()>, JoinHandle<()>);
+
+struct Batch {
+    client: Client,
+    config: BatchConfig,
+    rows: Vec<AnalyticsEventRow>,
+    /// writes failed in a row
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl EventBuffer {
+    pub fn new(client: Client, config: &BatchConfig) -> Self {
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let handle = tokio::spawn(run(Batch::new(client, config), rx, shutdown_rx));
+        Self {
+            tx,
+            shutdown: Arc::new(Mutex::new(Some((shutdown_tx, handle)))),
+        }
+    }
+
+    /// Queue rows for the next batch, waits while the buffer is full.
+    pub async fn push(&self, rows: Vec<AnalyticsEventRow>) -> Result<(), AppError> {
+        self.tx
+            .send(rows)
+            .await
+            .map_err(|_| anyhow::anyhow!("event buffer is closed"))?;
+        Ok(())
+    }
+
+    /// Stop taking rows and write the buffered ones. The state holds a sender for
+    /// the lifetime of the server, so the buffer has to be shut down explicitly.
+    pub async fn shutdown(&self) {
+        let Some((tx, handle)) = self.shutdown.lock().await.take() else {
+            return;
+        };
+        let _ = tx.send(());
+        if let Err(e) = handle.await {
+            warn!("Event buffer task failed: {}", e);
+        }
+    }
+}
+
+async fn run(
+    mut batch: Batch,
+    mut rx: mpsc::Receiver<Vec<AnalyticsEventRow>>,
+    mut shutdown: oneshot::Receiver<()>,
+) {
+    let mut interval = tokio::time::interval(Duration::from_millis(batch.config.flush_interval_ms));
+    loop {
+        tokio::select! {
+            ret = rx.recv() => match ret {
+                Some(rows) => {
+                    batch.push(rows);
+                    if batch.is_full() && batch.flush().await {
+                        interval.reset();
+                    }
+                }
+                // every sender is gone
+                None => break,
+            },
+            _ = interval.tick() => {
+                batch.flush().await;
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    // take what was queued before the shutdown, and write it all
+    rx.close();
+    while let Some(rows) = rx.recv().await {
+        batch.push(rows);
+    }
+    for _ in 0..SHUTDOWN_ATTEMPTS {
+        if let Some(at) = batch.retry_at {
+            tokio::time::sleep_until(at).await;
+        }
+        if batch.flush().await {
+            return;
+        }
+    }
+    warn!("Dropped {} events on shutdown", batch.rows.len());
+}
+
+impl Batch {
+    fn new(client: Client, config: &BatchConfig) -> Self {
+        Self {
+            client,
+            config: config.clone(),
+            rows: Vec::with_capacity(config.max_rows),
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    /// Buffer rows, the oldest ones are dropped beyond `max_buffered_rows`.
+    fn push(&mut self, rows: Vec<AnalyticsEventRow>) {
+        self.rows.extend(rows);
+        let excess = self
+            .rows
+            .len()
+            .saturating_sub(self.config.max_buffered_rows);
+        if excess > 0 {
+            warn!("Event buffer is full, dropped the {} oldest events", excess);
+            self.rows.drain(..excess);
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.rows.len() >= self.config.max_rows
+    }
+
+    /// Write the buffered rows, unless a failed write is waiting for its retry.
+    /// Returns whether the buffer is empty afterwards.
+    async fn flush(&mut self) -> bool {
+        if self.rows.is_empty() {
+            return true;
+        }
+        if self.retry_at.is_some_and(|at| at > Instant::now()) {
+            return false;
+        }
+        let len = self.rows.len();
+        match write(&self.client, &self.rows).await {
+            Ok(()) => {
+                info!("Wrote {} events", len);
+                self.rows.clear();
+                self.failures = 0;
+                self.retry_at = None;
+                true
+            }
+            Err(e) => {
+                self.failures += 1;
+                let backoff = self.backoff();
+                warn!(
+                    "Failed to write {} events, retrying in {:?}: {}",
+                    len, backoff, e
+                );
+                self.retry_at = Some(Instant::now() + backoff);
+                false
+            }
+        }
+    }
+
+    /// Flush interval doubled for every failed write in a row.
+    fn backoff(&self) -> Duration {
+        let base = Duration::from_millis(self.config.flush_interval_ms);
+        base.saturating_mul(1 << self.failures.min(16))
+            .min(MAX_BACKOFF)
+    }
+}
+
+async fn write(
+    client: &Client,
+    rows: &[AnalyticsEventRow],
+) -> Result<(), clickhouse::error::Error> {
+    let mut insert = client.insert(EVENTS_TABLE)?;
+    for row in rows {
+        insert.write(row).await?;
+    }
+    insert.end().await
+}
+
+#[cfg(test)]
+mod tests {
+    use clickhouse::test::{handlers, status, Mock};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn buffer_should_flush_by_size() -> anyhow::Result<()> {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+        let recording = mock.add(handlers::record());
+        // an interval long enough to never elapse during the test
+        let config = BatchConfig {
+            max_rows: 2,
+            flush_interval_ms: 3_600_000,
+            ..Default::default()
+        };
+        let buffer = EventBuffer::new(client, &config);
+
+        buffer.push(vec![row("client_1")]).await?;
+        buffer.push(vec![row("client_2")]).await?;
+
+        let rows: Vec<AnalyticsEventRow> = recording.collect().await;
+        let ids: Vec<_> = rows.iter().map(|r| r.client_id.as_str()).collect();
+        assert_eq!(ids, vec!["client_1", "client_2"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn buffer_should_retry_failed_writes() -> anyhow::Result<()> {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+        mock.add(handlers::failure(status::FORBIDDEN));
+        let recording = mock.add(handlers::record());
+        let config = BatchConfig {
+            max_rows: 1,
+            flush_interval_ms: 10,
+            ..Default::default()
+        };
+        let buffer = EventBuffer::new(client, &config);
+
+        buffer.push(vec![row("client_1")]).await?;
+
+        // the rows of the failed write are kept, and written by the retry
+        let rows: Vec<AnalyticsEventRow> = recording.collect().await;
+        let ids: Vec<_> = rows.iter().map(|r| r.client_id.as_str()).collect();
+        assert_eq!(ids, vec!["client_1"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn buffer_should_flush_on_shutdown() -> anyhow::Result<()> {
+        let mock = Mock::new();
+        let client = Client::default().with_url(mock.url());
+        let recording = mock.add(handlers::record());
+        let config = BatchConfig {
+            max_rows: 100,
+            flush_interval_ms: 3_600_000,
+            ..Default::default()
+        };
+        let buffer = EventBuffer::new(client, &config);
+
+        buffer.push(vec![row("client_1")]).await?;
+        buffer.shutdown().await;
+
+        let rows: Vec<AnalyticsEventRow> = recording.collect().await;
+        assert_eq!(rows.len(), 1);
+        assert!(buffer.push(vec![row("client_2")]).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn batch_should_drop_oldest_rows_beyond_its_bound() {
+        let config = BatchConfig {
+            max_buffered_rows: 2,
+            ..Default::default()
+        };
+        let mut batch = Batch::new(Client::default(), &config);
+        batch.push(vec![row("client_1"), row("client_2")]);
+        batch.push(vec![row("client_3")]);
+        let ids: Vec<_> = batch.rows.iter().map(|r| r.client_id.as_str()).collect();
+        assert_eq!(ids, vec!["client_2", "client_3"]);
+    }
+
+    fn row(client_id: &str) -> AnalyticsEventRow {
+        AnalyticsEventRow {
+            client_id: client_id.to_string(),
+            ..Default::default()
+        }
+    }
+}
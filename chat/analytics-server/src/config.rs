@@ -7,6 +7,20 @@ use serde::{Deserialize, Serialize};
 pub struct AppConfig {
     pub server: ServerConfig,
     pub auth: AuthConfig,
+    #[serde(default)]
+    pub batch: BatchConfig,
+}
+
+/// Events are buffered and written to ClickHouse in batches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchConfig {
+    /// flush once this many rows are buffered
+    pub max_rows: usize,
+    /// flush at least this often, in milliseconds
+    pub flush_interval_ms: u64,
+    /// rows kept while ClickHouse can't be written to, the oldest are dropped beyond
+    #[serde(default = "default_max_buffered_rows")]
+    pub max_buffered_rows: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,6 +37,20 @@ pub struct ServerConfig {
     pub base_dir: PathBuf,
 }
 
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_rows: 1000,
+            flush_interval_ms: 1000,
+            max_buffered_rows: default_max_buffered_rows(),
+        }
+    }
+}
+
+fn default_max_buffered_rows() -> usize {
+    100_000
+}
+
 impl AppConfig {
     pub fn load() -> Result<Self> {
         // read from  ./app.yml, or /etc/config/app.yml, or from env CHAT_CONFIG
@@ -25,6 +25,12 @@ pub enum AppError {
 
     #[error("miss event data")]
     MissEventData,
+
+    #[error("invalid query: {0}")]
+    InvalidQuery(String),
+
+    #[error("too many events: {0}, at most {1} per batch")]
+    TooManyEvents(usize, usize),
 }
 
 impl ErrorOutput {
@@ -42,6 +48,8 @@ impl IntoResponse for AppError {
             AppError::AnyError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::MissEventContext => StatusCode::BAD_REQUEST,
             AppError::MissEventData => StatusCode::BAD_REQUEST,
+            AppError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            AppError::TooManyEvents(..) => StatusCode::PAYLOAD_TOO_LARGE,
         };
 
         (status, Json(json!(ErrorOutput::new(self.to_string())))).into_response()
@@ -31,6 +31,7 @@ impl TryFrom<AnalyticsEvent> for AnalyticsEventRow {
 impl EventConsume for EventContext {
     fn consume(self, row: &mut AnalyticsEventRow) -> Result<(), AppError> {
         row.client_id = self.client_id;
+        row.session_id = self.session_id;
         row.app_version = self.app_version;
 
         if !self.user_id.is_empty() {
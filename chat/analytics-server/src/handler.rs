@@ -1,16 +1,25 @@
 use axum::{
-    extract::State,
+    extract::{Query, State},
     http::{request::Parts, StatusCode},
     response::IntoResponse,
+    Extension, Json,
 };
 use chat_core::User;
+use chrono::Utc;
 
-use crate::{extractors::Protobuf, pb::analytics::AnalyticsEvent, AppError, AppState};
+use crate::{
+    extractors::Protobuf,
+    pb::analytics::{AnalyticsEvent, AnalyticsEvents},
+    AppError, AppState, StatsQuery,
+};
 
 use clickhouse::Row;
 
 use serde::{Deserialize, Serialize};
 
+/// Events accepted in a single batch request.
+const MAX_BATCH_EVENTS: usize = 1000;
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize, Row)]
 pub struct AnalyticsEventRow {
     // EventContext fields
@@ -23,6 +32,8 @@ pub struct AnalyticsEventRow {
     pub system_locale: String,
     pub system_timezone: String,
     pub user_id: Option<String>,
+    /// workspace of the logged in user
+    pub workspace_id: Option<String>,
     pub ip: Option<String>,
     pub user_agent: Option<String>,
     pub geo_country: Option<String>,
@@ -97,14 +108,181 @@ pub(crate) async fn create_event_handler(
     State(state): State<AppState>,
     Protobuf(event): Protobuf<AnalyticsEvent>,
 ) -> Result<impl IntoResponse, AppError> {
-    let mut row = AnalyticsEventRow::try_from(event)?;
-    if let Some(user) = parts.extensions.get::<User>() {
-        row.user_id = Some(user.id.to_string());
-    } else {
-        row.user_id = None;
+    let row = AnalyticsEventRow::from_request(event, &parts)?;
+    state.buffer.push(vec![row]).await?;
+    Ok(StatusCode::CREATED)
+}
+
+/// Several events in one protobuf body, either all of them are accepted or none.
+#[utoipa::path(
+        post,
+        path = "/api/events",
+        responses(
+            (status = 201, description = "Events Created"),
+            (status = 400, description = "Invalid Event", body = ErrorOutput),
+            (status = 413, description = "Too Many Events", body = ErrorOutput),
+        ),
+        security(
+            ("token" = [])
+        )
+    )]
+pub(crate) async fn create_events_handler(
+    parts: Parts,
+    State(state): State<AppState>,
+    Protobuf(events): Protobuf<AnalyticsEvents>,
+) -> Result<impl IntoResponse, AppError> {
+    if events.events.len() > MAX_BATCH_EVENTS {
+        return Err(AppError::TooManyEvents(
+            events.events.len(),
+            MAX_BATCH_EVENTS,
+        ));
     }
-    let mut insert = state.client.insert("analytics")?;
-    insert.write(&row).await?;
-    insert.end().await?;
+    let rows = events
+        .events
+        .into_iter()
+        .map(|event| AnalyticsEventRow::from_request(event, &parts))
+        .collect::<Result<Vec<_>, _>>()?;
+    state.buffer.push(rows).await?;
     Ok(StatusCode::CREATED)
 }
+
+impl AnalyticsEventRow {
+    /// The row of an event received now, with the user of the request if logged in.
+    fn from_request(event: AnalyticsEvent, parts: &Parts) -> Result<Self, AppError> {
+        let mut row = Self::try_from(event)?;
+        row.server_ts = Utc::now().timestamp_millis();
+        if let Some(user) = parts.extensions.get::<User>() {
+            row.user_id = Some(user.id.to_string());
+            row.workspace_id = Some(user.ws_id.to_string());
+        } else {
+            row.user_id = None;
+        }
+        Ok(row)
+    }
+}
+
+/// Logged in users with any event per day, week or month, i.e. DAU, WAU or MAU.
+#[utoipa::path(
+        get,
+        path = "/api/stats/active_users",
+        params(StatsQuery),
+        responses(
+            (status = 200, description = "Active users per interval", body = Vec<ActiveUsers>),
+            (status = 400, description = "Invalid query", body = ErrorOutput),
+        ),
+        security(
+            ("token" = [])
+        )
+    )]
+pub(crate) async fn active_users_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(Json(state.active_users(user.ws_id, &query).await?))
+}
+
+/// Messages sent per day.
+#[utoipa::path(
+        get,
+        path = "/api/stats/messages",
+        params(StatsQuery),
+        responses(
+            (status = 200, description = "Messages per day", body = Vec<DailyMessages>),
+            (status = 400, description = "Invalid query", body = ErrorOutput),
+        ),
+        security(
+            ("token" = [])
+        )
+    )]
+pub(crate) async fn daily_messages_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(Json(state.daily_messages(user.ws_id, &query).await?))
+}
+
+/// Chats with the most messages.
+#[utoipa::path(
+        get,
+        path = "/api/stats/top_chats",
+        params(StatsQuery),
+        responses(
+            (status = 200, description = "Top chats", body = Vec<ChatMessages>),
+            (status = 400, description = "Invalid query", body = ErrorOutput),
+        ),
+        security(
+            ("token" = [])
+        )
+    )]
+pub(crate) async fn top_chats_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(Json(state.top_chats(user.ws_id, &query).await?))
+}
+
+/// Sessions per day and their average length in milliseconds.
+#[utoipa::path(
+        get,
+        path = "/api/stats/sessions",
+        params(StatsQuery),
+        responses(
+            (status = 200, description = "Sessions per day", body = Vec<DailySessions>),
+            (status = 400, description = "Invalid query", body = ErrorOutput),
+        ),
+        security(
+            ("token" = [])
+        )
+    )]
+pub(crate) async fn daily_sessions_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(Json(state.daily_sessions(user.ws_id, &query).await?))
+}
+
+/// Clients per app version.
+#[utoipa::path(
+        get,
+        path = "/api/stats/app_versions",
+        params(StatsQuery),
+        responses(
+            (status = 200, description = "Clients per app version", body = Vec<Breakdown>),
+            (status = 400, description = "Invalid query", body = ErrorOutput),
+        ),
+        security(
+            ("token" = [])
+        )
+    )]
+pub(crate) async fn app_versions_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(Json(state.app_versions(user.ws_id, &query).await?))
+}
+
+/// Clients per operating system.
+#[utoipa::path(
+        get,
+        path = "/api/stats/os",
+        params(StatsQuery),
+        responses(
+            (status = 200, description = "Clients per os", body = Vec<Breakdown>),
+            (status = 400, description = "Invalid query", body = ErrorOutput),
+        ),
+        security(
+            ("token" = [])
+        )
+    )]
+pub(crate) async fn os_breakdown_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    Ok(Json(state.os_breakdown(user.ws_id, &query).await?))
+}
@@ -1,3 +1,4 @@
+mod buffer;
 pub mod config;
 pub mod error;
 mod events;
@@ -5,23 +6,35 @@ mod extractors;
 pub mod handler;
 pub mod openapi;
 pub mod pb;
+mod stats;
 
+pub use buffer::EventBuffer;
 use clickhouse::Client;
 pub use config::*;
 pub use error::*;
+pub use stats::*;
 
 use anyhow::Context;
-use chat_core::{extract_user, set_layer, DecodingKey, TokenVerify, User};
-use handler::create_event_handler;
+use chat_core::{extract_user, set_layer, verify_token, DecodingKey, TokenVerify, User};
+use handler::*;
 use openapi::OpenApiRouter;
 
 use std::{fmt, ops::Deref, sync::Arc};
 use tokio::fs;
 use tower_http::cors::{Any, CorsLayer};
 
-use axum::{http::Method, middleware::from_fn_with_state, routing::post, Router};
+use axum::{
+    http::Method,
+    middleware::from_fn_with_state,
+    routing::{get, post},
+    Router,
+};
 pub use config::AppConfig;
 
+/// ClickHouse table of the events, see `protos/clickhouse.sql`. Events used to be
+/// inserted into a table named `analytics`, which has to be renamed on upgrade.
+pub(crate) const EVENTS_TABLE: &str = "analytics_events";
+
 #[derive(Debug, Clone)]
 pub struct AppState {
     inner: Arc<AppStateInner>,
@@ -32,6 +45,7 @@ pub struct AppStateInner {
     pub(crate) config: AppConfig,
     pub(crate) dk: DecodingKey,
     pub(crate) client: Client,
+    pub(crate) buffer: EventBuffer,
 }
 
 pub async fn get_router(state: AppState) -> Result<Router, AppError> {
@@ -46,9 +60,20 @@ pub async fn get_router(state: AppState) -> Result<Router, AppError> {
         .allow_headers(Any)
         .allow_origin(Any);
 
+    let stats = Router::new()
+        .route("/active_users", get(active_users_handler))
+        .route("/messages", get(daily_messages_handler))
+        .route("/top_chats", get(top_chats_handler))
+        .route("/sessions", get(daily_sessions_handler))
+        .route("/app_versions", get(app_versions_handler))
+        .route("/os", get(os_breakdown_handler))
+        .layer(from_fn_with_state(state.clone(), verify_token::<AppState>));
+
     let api = Router::new()
         .route("/event", post(create_event_handler))
+        .route("/events", post(create_events_handler))
         .layer(from_fn_with_state(state.clone(), extract_user::<AppState>))
+        .nest("/stats", stats)
         .layer(cors);
     let app = Router::new().openapi().nest("/api", api).with_state(state);
     Ok(set_layer(app))
@@ -85,12 +110,25 @@ impl AppState {
         if let Some(password) = &config.server.db_password {
             client = client.with_password(password);
         }
+        let buffer = EventBuffer::new(client.clone(), &config.batch);
         Ok(Self {
-            inner: Arc::new(AppStateInner { config, dk, client }),
+            inner: Arc::new(AppStateInner {
+                config,
+                dk,
+                client,
+                buffer,
+            }),
         })
     }
 }
 
+impl AppState {
+    /// Write the buffered events, once the server stopped taking requests.
+    pub async fn shutdown(&self) {
+        self.buffer.shutdown().await;
+    }
+}
+
 impl fmt::Debug for AppStateInner {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("AppStateInner")
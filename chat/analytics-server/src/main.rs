@@ -1,6 +1,6 @@
 use analytics_server::{get_router, AppConfig, AppState};
 use anyhow::Result;
-use tokio::net::TcpListener;
+use tokio::{net::TcpListener, signal};
 use tracing::{info, level_filters::LevelFilter};
 use tracing_subscriber::{
     fmt::Layer, layer::SubscriberExt as _, util::SubscriberInitExt as _, Layer as _,
@@ -15,9 +15,36 @@ async fn main() -> Result<()> {
 
     let state = AppState::try_new(config).await?;
 
-    let app = get_router(state).await?;
+    let app = get_router(state.clone()).await?;
     let listener = TcpListener::bind(&addr).await?;
     info!("Listening on: {}", addr);
-    axum::serve(listener, app.into_make_service()).await?;
+    axum::serve(listener, app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+    // the events of the last requests are still buffered
+    state.shutdown().await;
     Ok(())
 }
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutting down");
+}
@@ -8,7 +8,10 @@ use utoipa_rapidoc::RapiDoc;
 use utoipa_redoc::{Redoc, Servable};
 use utoipa_swagger_ui::SwaggerUi;
 
-use crate::{handler::*, AppState, ErrorOutput};
+use crate::{
+    handler::*, ActiveUsers, AppState, Breakdown, ChatMessages, DailyMessages, DailySessions,
+    ErrorOutput, Interval,
+};
 
 pub(crate) trait OpenApiRouter {
     fn openapi(self) -> Self;
@@ -18,8 +21,23 @@ pub(crate) trait OpenApiRouter {
 #[openapi(
     paths(
         create_event_handler,
+        create_events_handler,
+        active_users_handler,
+        daily_messages_handler,
+        top_chats_handler,
+        daily_sessions_handler,
+        app_versions_handler,
+        os_breakdown_handler,
     ),
-    components(schemas(ErrorOutput)),
+    components(schemas(
+        ErrorOutput,
+        ActiveUsers,
+        DailyMessages,
+        ChatMessages,
+        DailySessions,
+        Breakdown,
+        Interval
+    )),
     modifiers(&SecurityAddon),
     tags((name="chat", description="Chat operations")),
 )]
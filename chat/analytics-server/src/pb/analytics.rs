@@ -36,6 +36,12 @@ pub mod analytics_event {
         Navigation(super::NavigationEvent),
     }
 }
+/// / 批量上报的事件
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AnalyticsEvents {
+    #[prost(message, repeated, tag = "1")]
+    pub events: ::prost::alloc::vec::Vec<AnalyticsEvent>,
+}
 /// / 应用启动事件
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct AppStartEvent {}
@@ -178,6 +184,9 @@ pub struct EventContext {
     /// / 服务器时间戳
     #[prost(int64, tag = "9")]
     pub server_ts: i64,
+    /// / 会话 ID
+    #[prost(string, tag = "10")]
+    pub session_id: ::prost::alloc::string::String,
 }
 /// / 系统信息
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -0,0 +1,324 @@
+use chrono::{Duration, NaiveDate, Utc};
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{AppError, AppState, EVENTS_TABLE};
+
+const DEFAULT_RANGE_DAYS: i64 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+
+/// Filters of the stats api, the last 30 days by default. Stats are always of the
+/// caller's workspace.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+    /// first day, inclusive
+    #[param(value_type = Option<String>, example = "2024-12-01")]
+    pub from: Option<NaiveDate>,
+    /// last day, inclusive
+    #[param(value_type = Option<String>, example = "2024-12-31")]
+    pub to: Option<NaiveDate>,
+    /// number of rows of rankings, e.g. top chats
+    pub limit: Option<u32>,
+    /// bucket of time series, e.g. week for weekly active users
+    #[serde(default)]
+    pub interval: Interval,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Interval {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+/// Active users in an interval, daily, weekly or monthly.
+#[derive(Debug, Clone, Serialize, Deserialize, Row, ToSchema)]
+pub struct ActiveUsers {
+    /// first day of the interval
+    pub date: String,
+    pub users: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Row, ToSchema)]
+pub struct DailyMessages {
+    pub date: String,
+    pub messages: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Row, ToSchema)]
+pub struct ChatMessages {
+    pub chat_id: String,
+    pub messages: u64,
+    pub senders: u64,
+}
+
+/// Sessions started per day and their average length in milliseconds.
+#[derive(Debug, Clone, Serialize, Deserialize, Row, ToSchema)]
+pub struct DailySessions {
+    pub date: String,
+    pub sessions: u64,
+    pub avg_length: f64,
+}
+
+/// Clients per value of a dimension, e.g. app version or os.
+#[derive(Debug, Clone, Serialize, Deserialize, Row, ToSchema)]
+pub struct Breakdown {
+    pub value: String,
+    pub clients: u64,
+}
+
+impl StatsQuery {
+    /// Date range and workspace filter of events, to follow a `WHERE`.
+    fn filter(&self, ws_id: i64) -> Result<Filter, AppError> {
+        self.filter_on("toDate(server_ts)", ws_id)
+    }
+
+    /// Date range and workspace filter on the day given by `date`.
+    fn filter_on(&self, date: &str, ws_id: i64) -> Result<Filter, AppError> {
+        let to = self.to.unwrap_or_else(|| Utc::now().date_naive());
+        let from = self
+            .from
+            .unwrap_or_else(|| to - Duration::days(DEFAULT_RANGE_DAYS - 1));
+        if from > to {
+            return Err(AppError::InvalidQuery(format!(
+                "from {} is after to {}",
+                from, to
+            )));
+        }
+        Ok(Filter {
+            sql: format!("{date} BETWEEN toDate(?) AND toDate(?) AND workspace_id = ?"),
+            from: from.to_string(),
+            to: to.to_string(),
+            ws_id: ws_id.to_string(),
+        })
+    }
+
+    fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+
+struct Filter {
+    sql: String,
+    from: String,
+    to: String,
+    ws_id: String,
+}
+
+impl Filter {
+    fn bind(&self, query: clickhouse::query::Query) -> clickhouse::query::Query {
+        query.bind(&self.from).bind(&self.to).bind(&self.ws_id)
+    }
+}
+
+impl Interval {
+    fn start_of(&self) -> &'static str {
+        match self {
+            Interval::Day => "toDate(server_ts)",
+            Interval::Week => "toMonday(server_ts)",
+            Interval::Month => "toStartOfMonth(server_ts)",
+        }
+    }
+}
+
+impl AppState {
+    /// Logged in users with any event per interval, i.e. DAU, WAU or MAU.
+    pub async fn active_users(
+        &self,
+        ws_id: i64,
+        query: &StatsQuery,
+    ) -> Result<Vec<ActiveUsers>, AppError> {
+        let filter = query.filter(ws_id)?;
+        let sql = format!(
+            r#"
+            SELECT toString({start}) AS date, uniqExact(user_id) AS users
+            FROM {EVENTS_TABLE}
+            WHERE {filter} AND user_id IS NOT NULL
+            GROUP BY date
+            ORDER BY date
+            "#,
+            start = query.interval.start_of(),
+            filter = filter.sql,
+        );
+        let rows = filter
+            .bind(self.client.query(&sql))
+            .fetch_all::<ActiveUsers>()
+            .await?;
+        Ok(rows)
+    }
+
+    pub async fn daily_messages(
+        &self,
+        ws_id: i64,
+        query: &StatsQuery,
+    ) -> Result<Vec<DailyMessages>, AppError> {
+        let filter = query.filter(ws_id)?;
+        let sql = format!(
+            r#"
+            SELECT toString(toDate(server_ts)) AS date, count() AS messages
+            FROM {EVENTS_TABLE}
+            WHERE {filter} AND event_type = 'message_sent'
+            GROUP BY date
+            ORDER BY date
+            "#,
+            filter = filter.sql,
+        );
+        let rows = filter
+            .bind(self.client.query(&sql))
+            .fetch_all::<DailyMessages>()
+            .await?;
+        Ok(rows)
+    }
+
+    /// Chats with the most messages.
+    pub async fn top_chats(
+        &self,
+        ws_id: i64,
+        query: &StatsQuery,
+    ) -> Result<Vec<ChatMessages>, AppError> {
+        let filter = query.filter(ws_id)?;
+        let sql = format!(
+            r#"
+            SELECT assumeNotNull(message_chat_id) AS chat_id,
+                count() AS messages,
+                uniqExact(user_id) AS senders
+            FROM {EVENTS_TABLE}
+            WHERE {filter} AND event_type = 'message_sent' AND message_chat_id IS NOT NULL
+            GROUP BY chat_id
+            ORDER BY messages DESC, chat_id
+            LIMIT ?
+            "#,
+            filter = filter.sql,
+        );
+        let rows = filter
+            .bind(self.client.query(&sql))
+            .bind(query.limit())
+            .fetch_all::<ChatMessages>()
+            .await?;
+        Ok(rows)
+    }
+
+    /// Sessions per day they started on, with their average length.
+    pub async fn daily_sessions(
+        &self,
+        ws_id: i64,
+        query: &StatsQuery,
+    ) -> Result<Vec<DailySessions>, AppError> {
+        let filter = query.filter_on("date", ws_id)?;
+        let sql = format!(
+            r#"
+            SELECT toString(date) AS date,
+                count() AS sessions,
+                avg(session_length) AS avg_length
+            FROM session_lengths
+            WHERE {filter}
+            GROUP BY date
+            ORDER BY date
+            "#,
+            filter = filter.sql,
+        );
+        let rows = filter
+            .bind(self.client.query(&sql))
+            .fetch_all::<DailySessions>()
+            .await?;
+        Ok(rows)
+    }
+
+    pub async fn app_versions(
+        &self,
+        ws_id: i64,
+        query: &StatsQuery,
+    ) -> Result<Vec<Breakdown>, AppError> {
+        self.breakdown("app_version", ws_id, query).await
+    }
+
+    pub async fn os_breakdown(
+        &self,
+        ws_id: i64,
+        query: &StatsQuery,
+    ) -> Result<Vec<Breakdown>, AppError> {
+        self.breakdown("system_os", ws_id, query).await
+    }
+
+    /// Distinct clients per value of `column`, most used first.
+    async fn breakdown(
+        &self,
+        column: &str,
+        ws_id: i64,
+        query: &StatsQuery,
+    ) -> Result<Vec<Breakdown>, AppError> {
+        let filter = query.filter(ws_id)?;
+        let sql = format!(
+            r#"
+            SELECT {column} AS value, uniqExact(client_id) AS clients
+            FROM {EVENTS_TABLE}
+            WHERE {filter}
+            GROUP BY value
+            ORDER BY clients DESC, value
+            "#,
+            filter = filter.sql,
+        );
+        let rows = filter
+            .bind(self.client.query(&sql))
+            .fetch_all::<Breakdown>()
+            .await?;
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_should_default_to_last_30_days() -> anyhow::Result<()> {
+        let query = StatsQuery {
+            to: NaiveDate::from_ymd_opt(2024, 12, 31),
+            ..Default::default()
+        };
+        let filter = query.filter(1)?;
+        assert_eq!(
+            (filter.from.as_str(), filter.to.as_str()),
+            ("2024-12-02", "2024-12-31")
+        );
+        assert_eq!(query.limit(), DEFAULT_LIMIT);
+
+        let query = StatsQuery {
+            limit: Some(1000),
+            ..query
+        };
+        assert_eq!(query.limit(), MAX_LIMIT);
+        Ok(())
+    }
+
+    #[test]
+    fn filter_should_reject_inverted_range() {
+        let query = StatsQuery {
+            from: NaiveDate::from_ymd_opt(2024, 12, 31),
+            to: NaiveDate::from_ymd_opt(2024, 12, 1),
+            ..Default::default()
+        };
+        assert!(query.filter(1).is_err());
+    }
+
+    #[test]
+    fn filter_should_be_scoped_to_the_workspace() -> anyhow::Result<()> {
+        let filter = StatsQuery::default().filter(42)?;
+        assert!(filter.sql.ends_with("AND workspace_id = ?"));
+        assert_eq!(filter.ws_id, "42");
+        Ok(())
+    }
+
+    #[test]
+    fn filter_on_sessions_should_use_their_date() -> anyhow::Result<()> {
+        let filter = StatsQuery::default().filter_on("date", 42)?;
+        assert!(filter.sql.starts_with("date BETWEEN"));
+        assert!(filter.sql.ends_with("AND workspace_id = ?"));
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chat_core::Citation;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use swiftide::{
+    prompt::PromptTemplate,
+    query::{
+        self, answers, query_transformers, response_transformers,
+        search_strategies::SimilaritySingleEmbedding, states, Query,
+    },
+    traits::{Retrieve, SimplePrompt},
+};
+use swiftide_pgvector::{PgVector, RetrievedChunk};
+use tracing::info;
+
+use crate::{history::ChatHistory, BotConfig};
+
+/// A bot's answer, with the knowledge base files it is based on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Answer {
+    pub content: String,
+    pub citations: Vec<Citation>,
+}
+
+/// Retrieves from the knowledge base and keeps the retrieved chunks, so the answer can
+/// cite their files after the response transformers rewrote the documents.
+#[derive(Debug, Clone)]
+struct CitingRetriever {
+    store: PgVector,
+    retrieved: Arc<Mutex<Vec<RetrievedChunk>>>,
+}
+
+/// Answer a question with the bot's knowledge base, in the context of `history`.
+pub(crate) async fn ask(
+    pool: &PgPool,
+    config: &BotConfig,
+    question: &str,
+    history: &ChatHistory,
+) -> anyhow::Result<Answer> {
+    let client = config.ollama_client();
+    let retriever = CitingRetriever {
+        store: config.vector_store(pool)?,
+        retrieved: Default::default(),
+    };
+    let search_strategy = SimilaritySingleEmbedding::default()
+        .with_top_k(config.top_k as u64)
+        .to_owned();
+
+    // rewrite follow up questions with the conversation, so retrieval gets a standalone question
+    let question = if history.is_empty() {
+        question.to_string()
+    } else {
+        let question = client
+            .prompt(history.condense_prompt(question).into())
+            .await?;
+        info!("Rewrote question as: {}", question);
+        question
+    };
+
+    let answer = answers::Simple::builder()
+        .client(client.clone())
+        .prompt_template(answer_prompt(config.system_prompt.as_deref(), history))
+        .build()?;
+
+    let pipeline = query::Pipeline::from_search_strategy(search_strategy)
+        .then_transform_query(query_transformers::GenerateSubquestions::from_client(
+            client.clone(),
+        ))
+        .then_transform_query(query_transformers::Embed::from_client(client.clone()))
+        .then_retrieve(retriever.clone())
+        .then_transform_response(response_transformers::Summary::from_client(client.clone()))
+        .then_answer(answer);
+    let result = pipeline.query(question).await?;
+    let citations = citations(&retriever.retrieved.lock().unwrap());
+    Ok(Answer {
+        content: result.answer().to_string(),
+        citations,
+    })
+}
+
+#[async_trait]
+impl Retrieve<SimilaritySingleEmbedding> for CitingRetriever {
+    async fn retrieve(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding,
+        query: Query<states::Pending>,
+    ) -> anyhow::Result<Query<states::Retrieved>> {
+        let Some(embedding) = &query.embedding else {
+            anyhow::bail!("No embedding for query")
+        };
+        let chunks = self
+            .store
+            .search_similar(embedding, search_strategy.top_k())
+            .await?;
+        let documents = chunks.iter().map(|c| c.chunk.clone()).collect();
+        self.retrieved.lock().unwrap().extend(chunks);
+        Ok(query.retrieved_documents(documents))
+    }
+}
+
+/// Files of the retrieved chunks, in the order they were first retrieved.
+fn citations(chunks: &[RetrievedChunk]) -> Vec<Citation> {
+    let mut seen = HashSet::new();
+    chunks
+        .iter()
+        .filter(|c| seen.insert(c.path.as_str()))
+        .map(|c| Citation {
+            path: c.path.clone(),
+        })
+        .collect()
+}
+
+/// The answer prompt, with the bot's system prompt and the conversation so far in front of it.
+fn answer_prompt(system_prompt: Option<&str>, history: &ChatHistory) -> PromptTemplate {
+    let mut prompt = String::new();
+    if let Some(system_prompt) = system_prompt {
+        prompt.push_str(&format!("{{% raw %}}{}{{% endraw %}}\n\n", system_prompt));
+    }
+    if !history.is_empty() {
+        prompt.push_str(&format!(
+            "## Conversation so far\n{{% raw %}}{}{{% endraw %}}\n\n",
+            history.transcript()
+        ));
+    }
+    prompt.push_str(
+        "Answer the following question based on the context provided:
+{{ question }}
+
+## Constraints
+* Do not include any information that is not in the provided context.
+* If the question cannot be answered by the provided context, state that it cannot be answered.
+* Answer the question completely and format it as markdown.
+
+## Context
+
+{{ documents }}
+",
+    );
+    prompt.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn citations_should_dedupe_paths_in_retrieval_order() {
+        let chunks: Vec<_> = ["b.md", "a.md", "b.md"]
+            .into_iter()
+            .map(|path| RetrievedChunk {
+                path: path.to_string(),
+                chunk: "the same chunk".to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let paths: Vec<_> = citations(&chunks).into_iter().map(|c| c.path).collect();
+        assert_eq!(paths, vec!["b.md", "a.md"]);
+    }
+}
@@ -0,0 +1,301 @@
+use std::{collections::HashMap, ops::Deref, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware::from_fn_with_state,
+    response::IntoResponse,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use chat_core::{verify_token, DecodingKey, IndexingJob, ListenerMetrics, TokenVerify, User};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use tracing::warn;
+
+use crate::{answer::ask, history::ChatHistory, AppConfig, AppError, BotConfig};
+
+#[derive(Clone)]
+pub struct AppState(Arc<AppStateInner>);
+
+pub struct AppStateInner {
+    pub config: AppConfig,
+    pub dk: DecodingKey,
+    pub pool: PgPool,
+    /// shared by the chat and indexing job listeners
+    pub listener_metrics: ListenerMetrics,
+}
+
+/// A bot user with its configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BotInfo {
+    pub id: i64,
+    pub ws_id: i64,
+    pub fullname: String,
+    pub email: String,
+    pub config: BotConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AskQuestion {
+    pub question: String,
+}
+
+#[derive(Debug, FromRow)]
+struct BotUser {
+    id: i64,
+    ws_id: i64,
+    fullname: String,
+    email: String,
+}
+
+pub fn get_router(state: AppState) -> Router {
+    let api = Router::new()
+        .route("/bots", get(list_bots_handler))
+        .route("/bots/:id", get(get_bot_handler))
+        .route("/bots/:id/ask", post(ask_handler))
+        .route("/bots/:id/jobs", get(list_jobs_handler))
+        .route("/jobs/:id", get(get_job_handler));
+
+    // only the probes are open, the bots and their knowledge belong to a workspace
+    Router::new()
+        .route("/metrics", get(metrics_handler))
+        .nest("/api", api)
+        .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
+        .route("/health", get(health_handler))
+        .route("/ready", get(ready_handler))
+        .with_state(state)
+}
+
+/// Liveness, the process is up and serving requests.
+async fn health_handler() -> impl IntoResponse {
+    "ok"
+}
+
+/// Readiness, the database can be reached.
+async fn ready_handler(State(state): State<AppState>) -> impl IntoResponse {
+    match sqlx::query("SELECT 1").execute(&state.pool).await {
+        Ok(_) => (StatusCode::OK, "ok"),
+        Err(e) => {
+            warn!("Database is not reachable: {}", e);
+            (StatusCode::SERVICE_UNAVAILABLE, "database unavailable")
+        }
+    }
+}
+
+/// Counters of the Postgres listeners, e.g. how often they had to reconnect.
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.listener_metrics.stats())
+}
+
+async fn list_bots_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let bots = state.list_bots(user.ws_id).await?;
+    Ok(Json(bots))
+}
+
+async fn get_bot_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let bot = state.get_bot(user.ws_id, id).await?;
+    Ok(Json(bot))
+}
+
+/// Ask a bot a question outside of any chat, the answer is returned and not stored.
+async fn ask_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(input): Json<AskQuestion>,
+) -> Result<impl IntoResponse, AppError> {
+    if input.question.trim().is_empty() {
+        return Err(AppError::InvalidInput("question is empty".to_string()));
+    }
+    let bot = state.get_bot(user.ws_id, id).await?;
+    let answer = ask(
+        &state.pool,
+        &bot.config,
+        &input.question,
+        &ChatHistory::default(),
+    )
+    .await?;
+    Ok(Json(answer))
+}
+
+async fn list_jobs_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let bot = state.get_bot(user.ws_id, id).await?;
+    let jobs: Vec<IndexingJob> =
+        sqlx::query_as("SELECT * FROM indexing_jobs WHERE bot_id = $1 ORDER BY id DESC")
+            .bind(bot.id)
+            .fetch_all(&state.pool)
+            .await?;
+    Ok(Json(jobs))
+}
+
+async fn get_job_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    let job: Option<IndexingJob> = sqlx::query_as(
+        r#"
+        SELECT j.* FROM indexing_jobs j
+        JOIN users u ON u.id = j.bot_id
+        WHERE j.id = $1 AND u.ws_id = $2
+        "#,
+    )
+    .bind(id)
+    .bind(user.ws_id)
+    .fetch_optional(&state.pool)
+    .await?;
+    match job {
+        Some(job) => Ok(Json(job)),
+        None => Err(AppError::NotFound(format!(
+            "indexing job with id {} not found",
+            id
+        ))),
+    }
+}
+
+impl Deref for AppState {
+    type Target = AppStateInner;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl TokenVerify for AppState {
+    type Error = AppError;
+    fn verify(&self, token: &str) -> Result<User, Self::Error> {
+        self.dk.verify(token).map_err(AppError::JwtError)
+    }
+}
+
+impl AppState {
+    pub fn new(config: AppConfig, pool: PgPool) -> Self {
+        let dk = DecodingKey::load(&config.auth.pk).expect("Failed to load pk");
+        Self(Arc::new(AppStateInner {
+            config,
+            dk,
+            pool,
+            listener_metrics: ListenerMetrics::default(),
+        }))
+    }
+
+    /// All bots of a workspace, with the default configuration for bots without a row in
+    /// `bot_configs`.
+    pub async fn list_bots(&self, ws_id: i64) -> Result<Vec<BotInfo>, AppError> {
+        let users: Vec<BotUser> = sqlx::query_as(
+            "SELECT id, ws_id, fullname, email FROM users WHERE ws_id = $1 AND is_bot = TRUE ORDER BY id",
+        )
+        .bind(ws_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut configs: HashMap<i64, BotConfig> = sqlx::query_as::<_, BotConfig>(
+            r#"
+            SELECT bot_id, knowledge_table, prompt_model, embed_model, system_prompt, top_k
+            FROM bot_configs
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|config| (config.bot_id, config))
+        .collect();
+
+        Ok(users
+            .into_iter()
+            .map(|user| {
+                let config = configs
+                    .remove(&user.id)
+                    .unwrap_or_else(|| BotConfig::new(user.id));
+                BotInfo::new(user, config)
+            })
+            .collect())
+    }
+
+    /// A bot of a workspace, bots of other workspaces are not found.
+    pub async fn get_bot(&self, ws_id: i64, id: i64) -> Result<BotInfo, AppError> {
+        let user: Option<BotUser> = sqlx::query_as(
+            "SELECT id, ws_id, fullname, email FROM users WHERE id = $1 AND ws_id = $2 AND is_bot = TRUE",
+        )
+        .bind(id)
+        .bind(ws_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(user) = user else {
+            return Err(AppError::NotFound(format!("bot with id {} not found", id)));
+        };
+        let config = BotConfig::load(&self.pool, id).await?;
+        Ok(BotInfo::new(user, config))
+    }
+}
+
+impl BotInfo {
+    fn new(user: BotUser, config: BotConfig) -> Self {
+        Self {
+            id: user.id,
+            ws_id: user.ws_id,
+            fullname: user.fullname,
+            email: user.email,
+            config,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use sqlx_db_tester::TestPg;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn list_and_get_bots_should_work() -> anyhow::Result<()> {
+        let config = AppConfig::load()?;
+        let post = config.server.db_url.rfind('/').unwrap();
+        let tdb = TestPg::new(
+            config.server.db_url[..post].to_string(),
+            Path::new("../migrations"),
+        );
+        let state = AppState::new(config, tdb.get_pool().await);
+
+        let (id,): (i64,) = sqlx::query_as(
+            r#"
+            INSERT INTO users (ws_id, fullname, email, password_hash, is_bot)
+            VALUES (0, 'rust', 'rust@bot.org', '', TRUE)
+            RETURNING id
+            "#,
+        )
+        .fetch_one(&state.pool)
+        .await?;
+        sqlx::query("INSERT INTO bot_configs (bot_id, knowledge_table) VALUES ($1, 'rust_docs')")
+            .bind(id)
+            .execute(&state.pool)
+            .await?;
+
+        let bots = state.list_bots(0).await?;
+        assert_eq!(bots.len(), 1);
+        assert_eq!(bots[0].email, "rust@bot.org");
+        assert_eq!(bots[0].config.knowledge_table, "rust_docs");
+        assert!(state.list_bots(1).await?.is_empty());
+
+        assert_eq!(state.get_bot(0, id).await?, bots[0]);
+        assert!(state.get_bot(1, id).await.is_err());
+        let err = state.get_bot(0, id + 1).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Not found: bot with id {} not found", id + 1)
+        );
+        Ok(())
+    }
+}
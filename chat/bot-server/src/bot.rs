@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use swiftide::integrations::ollama::Ollama;
+use swiftide_pgvector::{PgVector, PgVectorBuilder};
+
+use crate::VECTOR_SIZE;
+
+const DEFAULT_KNOWLEDGE_TABLE: &str = "swiftide_rag";
+const DEFAULT_PROMPT_MODEL: &str = "llama3.2";
+const DEFAULT_EMBED_MODEL: &str = "nomic-embed-text";
+const DEFAULT_TOP_K: i32 = 5;
+
+/// Per bot configuration stored in `bot_configs`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct BotConfig {
+    pub bot_id: i64,
+    /// pgvector table holding the bot's knowledge base
+    pub knowledge_table: String,
+    pub prompt_model: String,
+    pub embed_model: String,
+    pub system_prompt: Option<String>,
+    /// number of chunks retrieved for each question
+    pub top_k: i32,
+}
+
+impl BotConfig {
+    /// Default configuration, used for bots without a row in `bot_configs`.
+    pub fn new(bot_id: i64) -> Self {
+        Self {
+            bot_id,
+            knowledge_table: DEFAULT_KNOWLEDGE_TABLE.to_string(),
+            prompt_model: DEFAULT_PROMPT_MODEL.to_string(),
+            embed_model: DEFAULT_EMBED_MODEL.to_string(),
+            system_prompt: None,
+            top_k: DEFAULT_TOP_K,
+        }
+    }
+
+    pub async fn load(pool: &PgPool, bot_id: i64) -> anyhow::Result<Self> {
+        let config: Option<Self> = sqlx::query_as(
+            r#"
+            SELECT bot_id, knowledge_table, prompt_model, embed_model, system_prompt, top_k
+            FROM bot_configs
+            WHERE bot_id = $1
+            "#,
+        )
+        .bind(bot_id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(config.unwrap_or_else(|| Self::new(bot_id)))
+    }
+
+    /// Check the configuration, the knowledge table is used as an SQL identifier.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let table = &self.knowledge_table;
+        let valid = table.len() <= 63
+            && table.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && table
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid {
+            anyhow::bail!("Invalid knowledge table name: {}", table);
+        }
+        if self.top_k <= 0 {
+            anyhow::bail!("Invalid top_k: {}", self.top_k);
+        }
+        Ok(())
+    }
+
+    /// The pgvector store of the bot's knowledge table.
+    pub fn vector_store(&self, pool: &PgPool) -> anyhow::Result<PgVector> {
+        self.validate()?;
+        let store = PgVectorBuilder::default()
+            .pool(pool.clone())
+            .table_name(self.knowledge_table.clone())
+            .vector_size(VECTOR_SIZE as _)
+            .build()?;
+        Ok(store)
+    }
+
+    pub fn ollama_client(&self) -> Ollama {
+        Ollama::default()
+            .with_default_embed_model(&self.embed_model)
+            .with_default_prompt_model(&self.prompt_model)
+            .to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use sqlx_db_tester::TestPg;
+
+    use super::*;
+    use crate::AppConfig;
+
+    #[test]
+    fn validate_should_reject_invalid_table() {
+        let mut config = BotConfig::new(1);
+        assert!(config.validate().is_ok());
+        config.knowledge_table = "docs; DROP TABLE users".to_string();
+        assert!(config.validate().is_err());
+        config.knowledge_table = "1docs".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn load_bot_config_should_work() -> anyhow::Result<()> {
+        let config = AppConfig::load()?;
+        let post = config.server.db_url.rfind('/').unwrap();
+        let tdb = TestPg::new(
+            config.server.db_url[..post].to_string(),
+            Path::new("../migrations"),
+        );
+        let pool = tdb.get_pool().await;
+
+        // bots without a config get the defaults
+        let bot = BotConfig::load(&pool, 0).await?;
+        assert_eq!(bot, BotConfig::new(0));
+
+        sqlx::query(
+            r#"
+            INSERT INTO bot_configs (bot_id, knowledge_table, system_prompt, top_k)
+            VALUES (0, 'rust_docs', 'You are a rust expert.', 3)
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+        let bot = BotConfig::load(&pool, 0).await?;
+        assert_eq!(bot.knowledge_table, "rust_docs");
+        assert_eq!(bot.system_prompt.as_deref(), Some("You are a rust expert."));
+        assert_eq!(bot.top_k, 3);
+        assert_eq!(bot.prompt_model, DEFAULT_PROMPT_MODEL);
+        Ok(())
+    }
+}
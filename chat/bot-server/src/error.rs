@@ -0,0 +1,50 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorOutput {
+    pub error: String,
+}
+
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[error("sql error: {0}")]
+    SqlxError(#[from] sqlx::Error),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("jwt error: {0}")]
+    JwtError(jwt_simple::Error),
+
+    #[error("{0}")]
+    AnyError(#[from] anyhow::Error),
+}
+
+impl ErrorOutput {
+    pub fn new(error: impl Into<String>) -> Self {
+        Self {
+            error: error.into(),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match &self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            AppError::JwtError(_) => StatusCode::FORBIDDEN,
+            AppError::SqlxError(_) | AppError::AnyError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, Json(ErrorOutput::new(self.to_string()))).into_response()
+    }
+}
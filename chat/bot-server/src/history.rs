@@ -0,0 +1,103 @@
+use chat_core::Message;
+use sqlx::PgPool;
+
+/// Number of earlier messages used as conversation context.
+const HISTORY_SIZE: i64 = 10;
+
+/// Recent messages between a user and a bot in a chat, oldest first.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChatHistory {
+    bot_id: i64,
+    messages: Vec<Message>,
+}
+
+impl ChatHistory {
+    /// Load the messages exchanged between the sender of `message` and the bot before it.
+    pub(crate) async fn load(
+        pool: &PgPool,
+        bot_id: i64,
+        message: &Message,
+    ) -> anyhow::Result<Self> {
+        let mut messages: Vec<Message> = sqlx::query_as(
+            r#"
+            SELECT id, chat_id, sender_id, content, modified_content, files, reply_to, citations, created_at
+            FROM messages
+            WHERE chat_id = $1
+            AND id < $2
+            AND sender_id IN ($3, $4)
+            ORDER BY id DESC
+            LIMIT $5
+            "#,
+        )
+        .bind(message.chat_id)
+        .bind(message.id)
+        .bind(message.sender_id)
+        .bind(bot_id)
+        .bind(HISTORY_SIZE)
+        .fetch_all(pool)
+        .await?;
+        messages.reverse();
+        Ok(Self { bot_id, messages })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Render the history as a transcript, one `user:` or `assistant:` line per message.
+    pub(crate) fn transcript(&self) -> String {
+        self.messages
+            .iter()
+            .map(|m| {
+                let role = if m.sender_id == self.bot_id {
+                    "assistant"
+                } else {
+                    "user"
+                };
+                format!("{}: {}", role, m.content)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Prompt asking to rewrite a follow up question into a standalone question.
+    pub(crate) fn condense_prompt(&self, question: &str) -> String {
+        format!(
+            "Given the following conversation and a follow up question, rephrase the follow up question to be a standalone question, in its original language.
+Only return the standalone question, nothing else.
+
+## Conversation
+{}
+
+## Follow up question
+{}
+",
+            self.transcript(),
+            question
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::message;
+
+    #[test]
+    fn transcript_should_work() {
+        let history = ChatHistory {
+            bot_id: 10,
+            messages: vec![
+                message(1, "what are the handlers?", None),
+                message(10, "1. auth 2. chat", None),
+            ],
+        };
+        assert_eq!(
+            history.transcript(),
+            "user: what are the handlers?\nassistant: 1. auth 2. chat"
+        );
+        assert!(history
+            .condense_prompt("and the second one?")
+            .contains("## Follow up question\nand the second one?"));
+    }
+}
@@ -1,45 +1,57 @@
+use std::path::PathBuf;
+
 use anyhow::Result;
-use bot_server::{AppConfig, VECTOR_SIZE};
+use bot_server::{AppConfig, BotConfig, Indexer};
+use clap::Parser;
 use sqlx::postgres::PgPoolOptions;
-use swiftide::{
-    indexing::{
-        self,
-        loaders::FileLoader,
-        transformers::{ChunkCode, Embed, MetadataQACode},
-    },
-    integrations,
-};
-use swiftide_pgvector::PgVector;
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::{
     fmt::Layer, layer::SubscriberExt as _, util::SubscriberInitExt as _, Layer as _,
 };
 
+/// Index source code and documents into a bot's knowledge base.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Directories or files to index, can be repeated
+    #[arg(short, long = "root", default_value = "./src")]
+    roots: Vec<PathBuf>,
+    /// File extensions to index, comma separated, e.g. rs,md,txt
+    #[arg(short, long, value_delimiter = ',', default_value = "rs")]
+    extensions: Vec<String>,
+    /// Bot whose configuration (knowledge table and models) is used
+    #[arg(short, long, default_value_t = 0)]
+    bot: i64,
+    /// Knowledge table to index into, overrides the bot's table
+    #[arg(short, long)]
+    table: Option<String>,
+    /// Skip files whose content didn't change since the last run
+    #[arg(short, long)]
+    incremental: bool,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let layer = Layer::new().with_filter(LevelFilter::INFO);
     tracing_subscriber::registry().with(layer).init();
 
+    let args = Args::parse();
     let config = AppConfig::load()?;
 
     let pool = PgPoolOptions::new().connect(&config.server.db_url).await?;
 
-    let ollama_client = integrations::ollama::Ollama::default()
-        .with_default_embed_model("nomic-embed-text")
-        .with_default_prompt_model("llama3.2")
-        .to_owned();
-
-    let store = PgVector::try_new(pool, VECTOR_SIZE as _).await?;
+    let mut bot = BotConfig::load(&pool, args.bot).await?;
+    if let Some(table) = args.table {
+        bot.knowledge_table = table;
+    }
 
-    indexing::Pipeline::from_loader(FileLoader::new("./src").with_extensions(&["rs"]))
-        .then(MetadataQACode::new(ollama_client.clone()))
-        .then_chunk(ChunkCode::try_for_language_and_chunk_size(
-            "rust",
-            10..2048,
-        )?)
-        .then_in_batch(Embed::new(ollama_client.clone()).with_batch_size(10))
-        .then_store_with(store)
-        .run()
+    let indexer = Indexer::try_new(pool, &bot).await?;
+    let stats = indexer
+        .index_paths(&args.roots, &args.extensions, args.incremental)
         .await?;
+    println!(
+        "indexed {} files, skipped {} unchanged, removed {} deleted",
+        stats.indexed, stats.skipped, stats.deleted
+    );
     Ok(())
 }
@@ -0,0 +1,354 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use ignore::Walk;
+use sha1::{Digest, Sha1};
+use sqlx::PgPool;
+use swiftide::{
+    indexing::{
+        self,
+        persist::MemoryStorage,
+        transformers::{ChunkCode, ChunkMarkdown, ChunkText, Embed, MetadataQACode},
+        IndexingStream, Node,
+    },
+    integrations::ollama::Ollama,
+    traits::{Loader, Persist},
+};
+use swiftide_pgvector::PgVector;
+use tracing::{info, warn};
+
+use crate::BotConfig;
+
+const CHUNK_RANGE: Range<usize> = 10..2048;
+const EMBED_BATCH_SIZE: usize = 10;
+
+/// How a document is split into chunks, decided by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DocumentKind {
+    /// Source code, chunked with the tree-sitter grammar of the language.
+    Code(&'static str),
+    Markdown,
+    Text,
+}
+
+impl DocumentKind {
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        let kind = match ext.to_lowercase().as_str() {
+            "rs" => Self::Code("rust"),
+            "py" => Self::Code("python"),
+            "ts" | "tsx" => Self::Code("typescript"),
+            "js" | "jsx" => Self::Code("javascript"),
+            "rb" => Self::Code("ruby"),
+            "java" => Self::Code("java"),
+            "md" | "markdown" => Self::Markdown,
+            "txt" | "text" | "pdf" => Self::Text,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_extension)
+    }
+}
+
+/// A document to index, with the sha1 of its content.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub path: String,
+    pub content: String,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexStats {
+    pub indexed: usize,
+    pub skipped: usize,
+    pub deleted: usize,
+}
+
+/// Indexes documents into the knowledge table of a bot.
+#[derive(Debug, Clone)]
+pub struct Indexer {
+    pool: PgPool,
+    store: PgVector,
+    client: Ollama,
+    table: String,
+}
+
+/// Loads already read documents into an indexing pipeline.
+#[derive(Clone)]
+struct DocumentLoader(Vec<Document>);
+
+impl Document {
+    pub fn new(path: impl Into<String>, content: impl Into<String>) -> Self {
+        let content = content.into();
+        let hash = hex::encode(Sha1::digest(content.as_bytes()));
+        Self {
+            path: path.into(),
+            content,
+            hash,
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let is_pdf = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"));
+        let content = if is_pdf {
+            pdf_extract::extract_text(path)?
+        } else {
+            std::fs::read_to_string(path)?
+        };
+        Ok(Self::new(path.to_string_lossy(), content))
+    }
+}
+
+impl Loader for DocumentLoader {
+    fn into_stream(self) -> IndexingStream {
+        let nodes = self
+            .0
+            .into_iter()
+            .map(|doc| {
+                let mut node = Node::new(doc.content);
+                node.path = doc.path.into();
+                node
+            })
+            .collect::<Vec<_>>();
+        Ok::<_, anyhow::Error>(nodes).into()
+    }
+}
+
+impl Indexer {
+    pub async fn try_new(pool: PgPool, config: &BotConfig) -> Result<Self> {
+        let store = config.vector_store(&pool)?;
+        store.setup().await?;
+        Ok(Self {
+            pool,
+            store,
+            client: config.ollama_client(),
+            table: config.knowledge_table.clone(),
+        })
+    }
+
+    pub fn store(&self) -> &PgVector {
+        &self.store
+    }
+
+    /// Index the files with one of `extensions` under `roots`.
+    ///
+    /// In incremental mode files whose content didn't change since the last run are
+    /// skipped, and files that were removed are deleted from the knowledge table.
+    pub async fn index_paths(
+        &self,
+        roots: &[PathBuf],
+        extensions: &[String],
+        incremental: bool,
+    ) -> Result<IndexStats> {
+        let mut stats = IndexStats::default();
+        let known = if incremental {
+            self.indexed_hashes().await?
+        } else {
+            HashMap::new()
+        };
+
+        let mut docs = Vec::new();
+        let mut seen = HashSet::new();
+        for path in walk(roots, extensions) {
+            let doc = match Document::read(&path) {
+                Ok(doc) => doc,
+                Err(e) => {
+                    warn!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            seen.insert(doc.path.clone());
+            if known.get(&doc.path) == Some(&doc.hash) {
+                stats.skipped += 1;
+                continue;
+            }
+            docs.push(doc);
+        }
+
+        if incremental {
+            let removed = known.keys().filter(|path| {
+                !seen.contains(*path)
+                    && roots.iter().any(|root| Path::new(path).starts_with(root))
+                    && !Path::new(path).exists()
+            });
+            for path in removed {
+                info!("Removing deleted file {}", path);
+                self.delete(path).await?;
+                stats.deleted += 1;
+            }
+        }
+
+        stats.indexed = self.index_documents(docs).await?;
+        info!("Indexed into {}: {:?}", self.table, stats);
+        Ok(stats)
+    }
+
+    /// Replace the chunks of each document, returns the number of indexed documents.
+    pub async fn index_documents(&self, docs: Vec<Document>) -> Result<usize> {
+        let mut by_kind: HashMap<DocumentKind, Vec<Document>> = HashMap::new();
+        for doc in docs {
+            match DocumentKind::from_path(Path::new(&doc.path)) {
+                Some(kind) => by_kind.entry(kind).or_default().push(doc),
+                None => warn!("Skipping {}: unsupported document type", doc.path),
+            }
+        }
+
+        let mut count = 0;
+        for (kind, docs) in by_kind {
+            let hashes = docs
+                .iter()
+                .map(|doc| (doc.path.clone(), doc.hash.clone()))
+                .collect::<Vec<_>>();
+
+            // embed every chunk before touching the table, so a failed run keeps the old ones
+            let mut nodes = self.build_nodes(kind, docs).await?;
+            for (path, hash) in hashes {
+                let nodes = nodes.remove(&path).unwrap_or_default();
+                self.store.replace_path(&path, &nodes).await?;
+                self.record(&path, &hash).await?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Delete a document from the knowledge table.
+    pub async fn delete(&self, path: &str) -> Result<()> {
+        self.store.delete_path(path).await?;
+        sqlx::query(r#"DELETE FROM indexed_files WHERE knowledge_table = $1 AND path = $2"#)
+            .bind(&self.table)
+            .bind(path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Chunk and embed the documents, returns the nodes of each path.
+    async fn build_nodes(
+        &self,
+        kind: DocumentKind,
+        docs: Vec<Document>,
+    ) -> Result<HashMap<String, Vec<Node>>> {
+        let storage = MemoryStorage::default();
+        let pipeline = indexing::Pipeline::from_loader(DocumentLoader(docs));
+        let pipeline = match kind {
+            DocumentKind::Code(lang) => pipeline
+                .then(MetadataQACode::new(self.client.clone()))
+                .then_chunk(ChunkCode::try_for_language_and_chunk_size(
+                    lang,
+                    CHUNK_RANGE,
+                )?),
+            DocumentKind::Markdown => {
+                pipeline.then_chunk(ChunkMarkdown::from_chunk_range(CHUNK_RANGE))
+            }
+            DocumentKind::Text => pipeline.then_chunk(ChunkText::from_chunk_range(CHUNK_RANGE)),
+        };
+        pipeline
+            .then_in_batch(Embed::new(self.client.clone()).with_batch_size(EMBED_BATCH_SIZE))
+            .then_store_with(storage.clone())
+            .run()
+            .await?;
+
+        let mut nodes: HashMap<String, Vec<Node>> = HashMap::new();
+        for node in storage.get_all_values().await {
+            let path = node.path.to_string_lossy().to_string();
+            nodes.entry(path).or_default().push(node);
+        }
+        Ok(nodes)
+    }
+
+    async fn indexed_hashes(&self) -> Result<HashMap<String, String>> {
+        let rows: Vec<(String, String)> =
+            sqlx::query_as(r#"SELECT path, hash FROM indexed_files WHERE knowledge_table = $1"#)
+                .bind(&self.table)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(rows.into_iter().collect())
+    }
+
+    async fn record(&self, path: &str, hash: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO indexed_files (knowledge_table, path, hash)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (knowledge_table, path) DO UPDATE SET hash = $3, indexed_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(&self.table)
+        .bind(path)
+        .bind(hash)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Files under `roots` with one of `extensions`, honoring `.gitignore`.
+fn walk(roots: &[PathBuf], extensions: &[String]) -> Vec<PathBuf> {
+    roots
+        .iter()
+        .flat_map(Walk::new)
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document_kind_should_follow_extension() {
+        assert_eq!(
+            DocumentKind::from_path(Path::new("src/lib.rs")),
+            Some(DocumentKind::Code("rust"))
+        );
+        assert_eq!(
+            DocumentKind::from_path(Path::new("README.MD")),
+            Some(DocumentKind::Markdown)
+        );
+        assert_eq!(
+            DocumentKind::from_path(Path::new("notes.txt")),
+            Some(DocumentKind::Text)
+        );
+        assert_eq!(
+            DocumentKind::from_path(Path::new("paper.pdf")),
+            Some(DocumentKind::Text)
+        );
+        assert_eq!(DocumentKind::from_path(Path::new("logo.png")), None);
+    }
+
+    #[test]
+    fn document_hash_should_follow_content() {
+        let a = Document::new("a.md", "hello");
+        let b = Document::new("b.md", "hello");
+        let c = Document::new("a.md", "hello world");
+        assert_eq!(a.hash, b.hash);
+        assert_ne!(a.hash, c.hash);
+        assert_eq!(a.hash.len(), 40);
+    }
+
+    #[test]
+    fn walk_should_filter_extensions() {
+        let files = walk(&[PathBuf::from("src")], &["RS".to_string()]);
+        assert!(files.contains(&PathBuf::from("src/indexing.rs")));
+        assert!(files.iter().all(|p| p.extension().unwrap() == "rs"));
+    }
+}
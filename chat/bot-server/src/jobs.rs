@@ -0,0 +1,305 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chat_core::{IndexingJob, ListenerMetrics, PgNotifications};
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+use crate::{AppConfig, BotConfig, Document, Indexer};
+
+/// How often a worker marks the jobs it runs as alive, and looks for stale jobs.
+const HEARTBEAT_SECS: u64 = 60;
+
+/// A running job not updated for this long was abandoned, e.g. by a worker which
+/// crashed or was restarted while indexing. Live jobs are updated every heartbeat.
+const STALE_JOB_TIMEOUT_SECS: i64 = 5 * HEARTBEAT_SECS as i64;
+
+/// Jobs indexed at the same time by a worker, each loads an embedding model.
+const MAX_CONCURRENT_JOBS: usize = 2;
+
+/// Process the indexing jobs created by chat_server.
+///
+/// The listener only wakes up the worker, which runs the jobs in their own tasks.
+/// Jobs abandoned by a worker are requeued every heartbeat.
+pub async fn setup_job_worker(config: &AppConfig, metrics: ListenerMetrics) -> Result<()> {
+    let db_url = &config.server.db_url;
+    let base_dir = config.server.base_dir.clone();
+    let mut listener = PgNotifications::connect(db_url, &["indexing_job_added"])
+        .await?
+        .with_metrics(metrics.clone());
+
+    let pool = PgPoolOptions::new().connect(db_url).await?;
+
+    let wake = Arc::new(Notify::new());
+    tokio::spawn(run_jobs(pool.clone(), base_dir, wake.clone()));
+    tokio::spawn(requeue_stale_jobs_periodically(pool, wake.clone()));
+    // jobs added while the server was down
+    wake.notify_one();
+    loop {
+        let notif = listener.recv().await;
+        if let Err(e) = notif.payload().parse::<i64>() {
+            warn!("Invalid indexing job id {}: {}", notif.payload(), e);
+            metrics.invalid_payload();
+            continue;
+        }
+        // also picks up jobs whose notification was lost while reconnecting
+        wake.notify_one();
+    }
+}
+
+/// Claim and run the pending jobs whenever woken up, wake-ups while busy are merged.
+async fn run_jobs(pool: PgPool, base_dir: PathBuf, wake: Arc<Notify>) {
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_JOBS));
+    loop {
+        wake.notified().await;
+        process_pending_jobs(&pool, &base_dir, &permits).await;
+    }
+}
+
+async fn requeue_stale_jobs_periodically(pool: PgPool, wake: Arc<Notify>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(HEARTBEAT_SECS));
+    loop {
+        interval.tick().await;
+        match requeue_stale_jobs(&pool, STALE_JOB_TIMEOUT_SECS).await {
+            Ok(0) => {}
+            Ok(n) => {
+                info!("Requeued {} stale indexing jobs", n);
+                wake.notify_one();
+            }
+            Err(e) => warn!("Failed to requeue stale indexing jobs: {}", e),
+        }
+    }
+}
+
+async fn process_pending_jobs(pool: &PgPool, base_dir: &Path, permits: &Arc<Semaphore>) {
+    let pending: Vec<(i64,)> =
+        match sqlx::query_as("SELECT id FROM indexing_jobs WHERE status = 'pending' ORDER BY id")
+            .fetch_all(pool)
+            .await
+        {
+            Ok(pending) => pending,
+            Err(e) => {
+                warn!("Failed to load pending indexing jobs: {}", e);
+                return;
+            }
+        };
+    for (id,) in pending {
+        // claim a job only once it can run, so other workers may take it meanwhile
+        let permit = permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore should not be closed");
+        match claim_job(pool, id).await {
+            Ok(Some(job)) => {
+                tokio::spawn(process_job(pool.clone(), base_dir.to_owned(), job, permit));
+            }
+            // already taken by another worker
+            Ok(None) => {}
+            Err(e) => warn!("Failed to claim indexing job {}: {}", id, e),
+        }
+    }
+}
+
+async fn process_job(
+    pool: PgPool,
+    base_dir: PathBuf,
+    job: IndexingJob,
+    _permit: OwnedSemaphorePermit,
+) {
+    info!("Indexing {} for bot {}", job.file, job.bot_id);
+
+    let heartbeat = tokio::spawn(heartbeat(pool.clone(), job.id));
+    let ret = index_job(&pool, &base_dir, &job).await;
+    heartbeat.abort();
+
+    let error = match ret {
+        Ok(()) => None,
+        Err(e) => {
+            warn!("Indexing job {} failed: {}", job.id, e);
+            Some(e.to_string())
+        }
+    };
+    if let Err(e) = finish_job(&pool, job.id, error).await {
+        warn!("Failed to update indexing job {}: {}", job.id, e);
+    }
+}
+
+/// Keep a running job from being requeued as stale while it is indexed.
+async fn heartbeat(pool: PgPool, id: i64) {
+    let mut interval = tokio::time::interval(Duration::from_secs(HEARTBEAT_SECS));
+    // the first tick completes at once, the job was just claimed
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Err(e) = touch_job(&pool, id).await {
+            warn!("Failed to refresh indexing job {}: {}", id, e);
+        }
+    }
+}
+
+async fn index_job(pool: &PgPool, base_dir: &Path, job: &IndexingJob) -> Result<()> {
+    let path = resolve_file(base_dir, &job.file)?;
+    // pdf extraction is cpu bound
+    let mut doc = tokio::task::spawn_blocking(move || Document::read(&path)).await??;
+    // cite the file by its url, which chat clients can open
+    doc.path = job.file.clone();
+
+    let config = BotConfig::load(pool, job.bot_id).await?;
+    let indexer = Indexer::try_new(pool.clone(), &config).await?;
+    indexer.index_documents(vec![doc]).await?;
+    Ok(())
+}
+
+async fn claim_job(pool: &PgPool, id: i64) -> Result<Option<IndexingJob>> {
+    let job = sqlx::query_as(
+        r#"
+        UPDATE indexing_jobs
+        SET status = 'running', updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND status = 'pending'
+        RETURNING *
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(job)
+}
+
+async fn touch_job(pool: &PgPool, id: i64) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE indexing_jobs
+        SET updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND status = 'running'
+        "#,
+    )
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Set running jobs which were not updated for `timeout_secs` back to pending.
+async fn requeue_stale_jobs(pool: &PgPool, timeout_secs: i64) -> Result<u64> {
+    let ret = sqlx::query(
+        r#"
+        UPDATE indexing_jobs
+        SET status = 'pending', updated_at = CURRENT_TIMESTAMP
+        WHERE status = 'running'
+            AND updated_at < CURRENT_TIMESTAMP - $1::bigint * INTERVAL '1 second'
+        "#,
+    )
+    .bind(timeout_secs)
+    .execute(pool)
+    .await?;
+    Ok(ret.rows_affected())
+}
+
+async fn finish_job(pool: &PgPool, id: i64, error: Option<String>) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE indexing_jobs
+        SET status = CASE WHEN $2::text IS NULL THEN 'done' ELSE 'failed' END::indexing_job_status,
+            error = $2,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(error)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Path of an uploaded file from its url, e.g. `/files/1/abc/def/0123.md`.
+fn resolve_file(base_dir: &Path, url: &str) -> Result<PathBuf> {
+    let path = url
+        .strip_prefix("/files/")
+        .map(Path::new)
+        .filter(|p| p.components().all(|c| matches!(c, Component::Normal(_))))
+        .ok_or_else(|| anyhow!("Invalid file url: {}", url))?;
+    Ok(base_dir.join(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use chat_core::IndexingJobStatus;
+    use sqlx_db_tester::TestPg;
+
+    use super::*;
+
+    #[test]
+    fn resolve_file_should_work() {
+        let base_dir = Path::new("/tmp/chat_server");
+        assert_eq!(
+            resolve_file(base_dir, "/files/1/abc/def/0123.md").unwrap(),
+            PathBuf::from("/tmp/chat_server/1/abc/def/0123.md")
+        );
+        assert!(resolve_file(base_dir, "1/abc/def/0123.md").is_err());
+        assert!(resolve_file(base_dir, "/files/../etc/passwd").is_err());
+        assert!(resolve_file(base_dir, "/files//etc/passwd").is_err());
+    }
+
+    #[tokio::test]
+    async fn stale_running_jobs_should_be_requeued() -> anyhow::Result<()> {
+        let config = AppConfig::load()?;
+        let post = config.server.db_url.rfind('/').unwrap();
+        let tdb = TestPg::new(
+            config.server.db_url[..post].to_string(),
+            Path::new("../migrations"),
+        );
+        let pool = tdb.get_pool().await;
+
+        let (bot_id,): (i64,) = sqlx::query_as(
+            r#"
+            INSERT INTO users (ws_id, fullname, email, password_hash, is_bot)
+            VALUES (0, 'rust', 'rust@bot.org', '', TRUE)
+            RETURNING id
+            "#,
+        )
+        .fetch_one(&pool)
+        .await?;
+        let ids: Vec<(i64,)> = sqlx::query_as(
+            r#"
+            INSERT INTO indexing_jobs (bot_id, created_by, file, status, updated_at)
+            VALUES ($1, $1, '/files/1/a.md', 'running', CURRENT_TIMESTAMP - INTERVAL '2 hours'),
+                ($1, $1, '/files/1/b.md', 'running', CURRENT_TIMESTAMP)
+            RETURNING id
+            "#,
+        )
+        .bind(bot_id)
+        .fetch_all(&pool)
+        .await?;
+
+        let live_id = ids[1].0;
+        assert_eq!(requeue_stale_jobs(&pool, STALE_JOB_TIMEOUT_SECS).await?, 1);
+        for ((id,), status) in ids
+            .into_iter()
+            .zip([IndexingJobStatus::Pending, IndexingJobStatus::Running])
+        {
+            let job: IndexingJob = sqlx::query_as("SELECT * FROM indexing_jobs WHERE id = $1")
+                .bind(id)
+                .fetch_one(&pool)
+                .await?;
+            assert_eq!(job.status, status);
+        }
+
+        // a live job refreshed by its heartbeat is not stale
+        sqlx::query(
+            "UPDATE indexing_jobs SET updated_at = CURRENT_TIMESTAMP - INTERVAL '2 hours' WHERE id = $1",
+        )
+        .bind(live_id)
+        .execute(&pool)
+        .await?;
+        touch_job(&pool, live_id).await?;
+        assert_eq!(requeue_stale_jobs(&pool, STALE_JOB_TIMEOUT_SECS).await?, 0);
+        Ok(())
+    }
+}
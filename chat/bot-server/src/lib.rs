@@ -1,7 +1,40 @@
+mod answer;
+mod api;
+mod bot;
 mod config;
+mod error;
+mod history;
+mod indexing;
+mod jobs;
 mod notif;
 
+pub use answer::Answer;
+pub use api::*;
+pub use bot::*;
 pub use config::*;
+pub use error::*;
+pub use indexing::*;
+pub use jobs::*;
 pub use notif::*;
 
 pub const VECTOR_SIZE: usize = 768;
+
+#[cfg(test)]
+mod test_util {
+    use chat_core::Message;
+
+    /// A message of chat 1, as stored by chat_server.
+    pub(crate) fn message(sender_id: i64, content: &str, reply_to: Option<i64>) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "chat_id": 1,
+            "sender_id": sender_id,
+            "content": content,
+            "modified_content": null,
+            "files": [],
+            "reply_to": reply_to,
+            "created_at": "2024-11-22T10:00:00Z",
+        }))
+        .unwrap()
+    }
+}
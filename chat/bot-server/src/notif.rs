@@ -1,21 +1,15 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use chat_core::Message;
+use chat_core::{mention_handle, mentions, ListenerMetrics, Message, NotifyEvent, PgNotifications};
 use serde::{Deserialize, Serialize};
 use sqlx::{
-    postgres::{PgListener, PgPoolOptions},
+    postgres::{PgNotification, PgPoolOptions},
+    types::Json,
     PgPool,
 };
-use swiftide::{
-    integrations,
-    query::{self, answers, query_transformers, response_transformers},
-    traits::{EmbeddingModel, SimplePrompt},
-};
-use swiftide_pgvector::PgVectorBuilder;
-use tokio_stream::StreamExt;
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::{AppConfig, VECTOR_SIZE};
+use crate::{answer::ask, history::ChatHistory, AppConfig, BotConfig};
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -30,102 +24,352 @@ struct ChatMessageAdded {
     message: Message,
 }
 
-pub async fn setup_pg_listener(config: &AppConfig) -> anyhow::Result<()> {
-    let db_url = &config.server.db_url;
-    let mut listener = PgListener::connect(db_url).await?;
-    listener.listen("chat_message_added").await?;
-    info!("Listening to chat_message_added");
-
-    let pool = PgPoolOptions::new().connect(db_url).await?;
-    let bots = get_bots(&pool).await?;
-    let ollama_client = integrations::ollama::Ollama::default()
-        .with_default_embed_model("nomic-embed-text")
-        .with_default_prompt_model("llama3.2")
-        .to_owned();
+#[derive(Debug, Serialize, Deserialize)]
+struct UserUpdated {
+    op: String,
+    id: i64,
+    email: String,
+    is_bot: bool,
+}
 
-    let mut stream = listener.into_stream();
+/// Known bots, by user id, with the handle they are mentioned by.
+type Bots = HashMap<i64, String>;
 
-    while let Some(Ok(notif)) = stream.next().await {
+pub async fn setup_pg_listener(config: &AppConfig, metrics: ListenerMetrics) -> anyhow::Result<()> {
+    let (mut listener, pool, mut bots) = connect(&config.server.db_url, &metrics).await?;
+    loop {
+        let notif = listener.recv().await;
         info!("Received notification: {:?}", notif);
-        if let Some(notification) = Notification::load(notif.channel(), notif.payload(), &bots) {
+        for notification in handle_notification(&notif, &mut bots, &pool, &metrics).await {
             let pool = pool.clone();
-            let ollama_client = ollama_client.clone();
             tokio::spawn(async move {
-                notification
-                    .process(&pool, ollama_client.clone(), ollama_client.clone())
-                    .await
+                if let Err(e) = notification.process(&pool).await {
+                    warn!("Failed to process notification: {}", e);
+                }
             });
         }
     }
+}
 
-    Ok(())
+/// Listen for chat messages and bot changes, and load the known bots.
+async fn connect(
+    db_url: &str,
+    metrics: &ListenerMetrics,
+) -> anyhow::Result<(PgNotifications, PgPool, Bots)> {
+    // listen before loading the bots, so a bot created in between isn't missed
+    let listener = PgNotifications::connect(db_url, &["chat_message_added", "user_updated"])
+        .await?
+        .with_metrics(metrics.clone());
+
+    let pool = PgPoolOptions::new().connect(db_url).await?;
+    let bots = get_bots(&pool).await?;
+    Ok((listener, pool, bots))
+}
+
+/// Track a bot change, or the bots to answer a new message.
+async fn handle_notification(
+    notif: &PgNotification,
+    bots: &mut Bots,
+    pool: &PgPool,
+    metrics: &ListenerMetrics,
+) -> Vec<Notification> {
+    if notif.channel() == "user_updated" {
+        if let Err(e) = update_bots(bots, notif.payload()) {
+            warn!("Failed to update bots: {}", e);
+            metrics.invalid_payload();
+        }
+        return vec![];
+    }
+    let id = match NotifyEvent::id_of(notif) {
+        Ok(id) => id,
+        Err(e) => {
+            warn!("Skipping invalid notification {:?}: {}", notif, e);
+            metrics.invalid_payload();
+            return vec![];
+        }
+    };
+    // a database error says nothing about the payload, it isn't counted as invalid
+    let event = match NotifyEvent::fetch(pool, id).await {
+        Ok(Some(event)) => event,
+        Ok(None) => {
+            warn!("Event {} was pruned before it was processed", id);
+            return vec![];
+        }
+        Err(e) => {
+            warn!("Failed to load event {}: {}", id, e);
+            return vec![];
+        }
+    };
+    match Notification::load(&event.channel, &event.payload, bots, pool).await {
+        Ok(notifications) => notifications,
+        Err(e) => {
+            warn!("Failed to load notification: {}", e);
+            metrics.invalid_payload();
+            vec![]
+        }
+    }
 }
 
 impl Notification {
-    fn load(r#type: &str, payload: &str, bots: &HashSet<i64>) -> Option<Self> {
+    async fn load(
+        r#type: &str,
+        payload: &str,
+        bots: &Bots,
+        pool: &PgPool,
+    ) -> anyhow::Result<Vec<Self>> {
         match r#type {
             "chat_message_added" => {
-                let payload = serde_json::from_str::<ChatMessageAdded>(payload).ok()?;
-                let mut members = payload.members;
-                members.remove(&payload.message.sender_id);
-                // only process if it's a direct message
-                if members.len() == 1 {
-                    let bot_id = members.iter().next().unwrap();
-                    if bots.contains(bot_id) {
-                        return Some(Self {
-                            bot_id: *bot_id,
-                            event: payload.message,
-                        });
-                    }
+                let payload = serde_json::from_str::<ChatMessageAdded>(payload)?;
+                let message = payload.message;
+                // never answer bots, so bots can't trigger each other in a loop
+                if bots.contains_key(&message.sender_id) {
+                    return Ok(vec![]);
                 }
-                None
+                let replied_sender = match message.reply_to {
+                    Some(id) => get_message_sender(pool, id).await?,
+                    None => None,
+                };
+                let bot_ids = triggered_bots(&message, &payload.members, bots, replied_sender);
+                Ok(bot_ids
+                    .into_iter()
+                    .map(|bot_id| Self {
+                        bot_id,
+                        event: message.clone(),
+                    })
+                    .collect())
             }
-            _ => None,
+            _ => Ok(vec![]),
         }
     }
-    async fn process(
-        self,
-        pool: &PgPool,
-        client: impl SimplePrompt + Clone + 'static,
-        fastembed: impl EmbeddingModel + Clone + 'static,
-    ) -> anyhow::Result<()> {
-        let store = PgVectorBuilder::default()
-            .pool(pool.clone())
-            .vector_size(VECTOR_SIZE as _)
-            .build()?;
-        let pipeline = query::Pipeline::default()
-            .then_transform_query(query_transformers::GenerateSubquestions::from_client(
-                client.clone(),
-            ))
-            .then_transform_query(query_transformers::Embed::from_client(fastembed.clone()))
-            .then_retrieve(store)
-            .then_transform_response(response_transformers::Summary::from_client(client.clone()))
-            .then_answer(answers::Simple::from_client(client.clone()));
-        info!("Processing message: {:?}", self.event.id);
-        let result = pipeline.query(&self.event.content).await?;
-        let summary = result.answer();
-        info!("Got answer, writing to db");
+
+    async fn process(self, pool: &PgPool) -> anyhow::Result<()> {
+        let config = BotConfig::load(pool, self.bot_id).await?;
+        let history = ChatHistory::load(pool, self.bot_id, &self.event).await?;
+        info!(
+            "Processing message {:?} with bot {}",
+            self.event.id, self.bot_id
+        );
+        let answer = ask(pool, &config, &self.event.content, &history).await?;
+        info!(
+            "Got answer with {} citations, writing to db",
+            answer.citations.len()
+        );
 
         let _: (i64,) = sqlx::query_as(
             r#"
-                INSERT INTO messages (chat_id, sender_id, content)
-                VALUES ($1, $2, $3)
+                INSERT INTO messages (chat_id, sender_id, content, reply_to, citations)
+                VALUES ($1, $2, $3, $4, $5)
                 RETURNING id
                 "#,
         )
         .bind(self.event.chat_id)
         .bind(self.bot_id)
-        .bind(summary)
+        .bind(answer.content)
+        .bind(self.event.id)
+        .bind(Json(answer.citations))
         .fetch_one(pool)
         .await?;
         Ok(())
     }
 }
 
-async fn get_bots(pool: &PgPool) -> anyhow::Result<HashSet<i64>> {
-    let bots: Vec<(i64,)> =
-        sqlx::query_as::<_, (i64,)>(r#"SELECT id FROM users WHERE is_bot = TRUE"#)
+/// Bots to answer a message: the bot of a direct message, or in group chats and
+/// channels the bots mentioned as `@handle` or whose message is replied to.
+fn triggered_bots(
+    message: &Message,
+    members: &HashSet<i64>,
+    bots: &Bots,
+    replied_sender: Option<i64>,
+) -> Vec<i64> {
+    if bots.contains_key(&message.sender_id) {
+        return vec![];
+    }
+    let mut members = members.clone();
+    members.remove(&message.sender_id);
+    if members.len() == 1 {
+        let id = members.iter().next().unwrap();
+        return if bots.contains_key(id) {
+            vec![*id]
+        } else {
+            vec![]
+        };
+    }
+    let mut ids: Vec<i64> = members
+        .into_iter()
+        .filter(|id| match bots.get(id) {
+            Some(handle) => replied_sender == Some(*id) || mentions(&message.content, handle),
+            None => false,
+        })
+        .collect();
+    ids.sort();
+    ids
+}
+
+async fn get_message_sender(pool: &PgPool, id: i64) -> anyhow::Result<Option<i64>> {
+    let sender: Option<(i64,)> = sqlx::query_as(r#"SELECT sender_id FROM messages WHERE id = $1"#)
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(sender.map(|(id,)| id))
+}
+
+async fn get_bots(pool: &PgPool) -> anyhow::Result<Bots> {
+    let bots: Vec<(i64, String)> =
+        sqlx::query_as(r#"SELECT id, email FROM users WHERE is_bot = TRUE"#)
             .fetch_all(pool)
             .await?;
-    Ok(bots.into_iter().map(|(id,)| id).collect())
+    Ok(bots
+        .into_iter()
+        .map(|(id, email)| (id, mention_handle(&email)))
+        .collect())
+}
+
+/// Apply a `user_updated` notification to the known bots.
+fn update_bots(bots: &mut Bots, payload: &str) -> anyhow::Result<()> {
+    let payload = serde_json::from_str::<UserUpdated>(payload)?;
+    if payload.op != "DELETE" && payload.is_bot {
+        if bots
+            .insert(payload.id, mention_handle(&payload.email))
+            .is_none()
+        {
+            info!("Bot {} added", payload.id);
+        }
+    } else if bots.remove(&payload.id).is_some() {
+        info!("Bot {} removed", payload.id);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use sqlx::postgres::PgListener;
+    use sqlx_db_tester::TestPg;
+
+    use super::*;
+    use crate::test_util::message;
+
+    #[test]
+    fn update_bots_should_work() -> anyhow::Result<()> {
+        let mut bots = Bots::new();
+        update_bots(
+            &mut bots,
+            r#"{"op":"INSERT","id":1,"email":"Rust@bot.org","is_bot":true}"#,
+        )?;
+        update_bots(
+            &mut bots,
+            r#"{"op":"INSERT","id":2,"email":"a@zzq.com","is_bot":false}"#,
+        )?;
+        assert_eq!(bots, Bots::from([(1, "rust".to_string())]));
+
+        update_bots(
+            &mut bots,
+            r#"{"op":"UPDATE","id":1,"email":"rust@bot.org","is_bot":false}"#,
+        )?;
+        assert!(bots.is_empty());
+
+        update_bots(
+            &mut bots,
+            r#"{"op":"UPDATE","id":2,"email":"a@zzq.com","is_bot":true}"#,
+        )?;
+        update_bots(
+            &mut bots,
+            r#"{"op":"DELETE","id":2,"email":"a@zzq.com","is_bot":true}"#,
+        )?;
+        assert!(bots.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn triggered_bots_should_work() {
+        let bots = Bots::from([(10, "rust".to_string()), (11, "python".to_string())]);
+
+        // direct message
+        let msg = message(1, "hello", None);
+        assert_eq!(
+            triggered_bots(&msg, &HashSet::from([1, 10]), &bots, None),
+            vec![10]
+        );
+
+        // group chat needs a mention or a reply
+        let members = HashSet::from([1, 2, 10, 11]);
+        assert!(triggered_bots(&msg, &members, &bots, None).is_empty());
+        let msg = message(1, "@rust @python hello", None);
+        assert_eq!(triggered_bots(&msg, &members, &bots, None), vec![10, 11]);
+        let msg = message(1, "and the second one?", Some(5));
+        assert_eq!(triggered_bots(&msg, &members, &bots, Some(11)), vec![11]);
+
+        // bots never trigger bots
+        let msg = message(10, "@python hello", None);
+        assert!(triggered_bots(&msg, &members, &bots, None).is_empty());
+        let msg = message(10, "hello", None);
+        assert!(triggered_bots(&msg, &HashSet::from([10, 11]), &bots, None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn bot_created_after_startup_should_be_tracked() -> anyhow::Result<()> {
+        let config = AppConfig::load()?;
+        let post = config.server.db_url.rfind('/').unwrap();
+        let tdb = TestPg::new(
+            config.server.db_url[..post].to_string(),
+            Path::new("../migrations"),
+        );
+        let metrics = ListenerMetrics::default();
+        let (mut listener, pool, mut bots) = connect(&tdb.url(), &metrics).await?;
+        assert!(bots.is_empty());
+
+        let (id,): (i64,) = sqlx::query_as(
+            r#"
+            INSERT INTO users (ws_id, fullname, email, password_hash, is_bot)
+            VALUES (0, 'bot', 'test@bot.org', '', TRUE)
+            RETURNING id
+            "#,
+        )
+        .fetch_one(&pool)
+        .await?;
+
+        let notif = listener.recv().await;
+        let notifications = handle_notification(&notif, &mut bots, &pool, &metrics).await;
+        assert!(notifications.is_empty());
+        assert_eq!(bots.get(&id).map(String::as_str), Some("test"));
+        assert_eq!(metrics.stats().invalid_payloads, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn large_message_should_be_notified_by_id() -> anyhow::Result<()> {
+        let config = AppConfig::load()?;
+        let post = config.server.db_url.rfind('/').unwrap();
+        let tdb = TestPg::new(
+            config.server.db_url[..post].to_string(),
+            Path::new("../migrations"),
+        );
+        let pool = tdb.get_pool().await;
+
+        let mut listener = PgListener::connect(&tdb.url()).await?;
+        listener.listen("chat_message_added").await?;
+        let (chat_id,): (i64,) = sqlx::query_as(
+            "INSERT INTO chats (ws_id, type, members) VALUES (0, 'group', '{0}') RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await?;
+        // well above the 8000 byte limit of pg_notify payloads
+        let content = "rust ".repeat(4000);
+        sqlx::query("INSERT INTO messages (chat_id, sender_id, content) VALUES ($1, 0, $2)")
+            .bind(chat_id)
+            .bind(&content)
+            .execute(&pool)
+            .await?;
+
+        let notif = listener.recv().await?;
+        let event = NotifyEvent::fetch(&pool, NotifyEvent::id_of(&notif)?)
+            .await?
+            .unwrap();
+        assert_eq!(event.channel, "chat_message_added");
+        let payload = serde_json::from_str::<ChatMessageAdded>(&event.payload)?;
+        assert_eq!(payload.message.content, content);
+        assert_eq!(payload.members, HashSet::from([0]));
+        Ok(())
+    }
 }
@@ -1,6 +1,8 @@
 use anyhow::Result;
-use bot_server::{setup_pg_listener, AppConfig};
-use tracing::level_filters::LevelFilter;
+use bot_server::{get_router, setup_job_worker, setup_pg_listener, AppConfig, AppState};
+use sqlx::postgres::PgPoolOptions;
+use tokio::net::TcpListener;
+use tracing::{info, level_filters::LevelFilter};
 use tracing_subscriber::{
     fmt::Layer, layer::SubscriberExt as _, util::SubscriberInitExt as _, Layer as _,
 };
@@ -10,6 +12,22 @@ async fn main() -> Result<()> {
     let layer = Layer::new().with_filter(LevelFilter::INFO);
     tracing_subscriber::registry().with(layer).init();
     let config = AppConfig::load()?;
-    setup_pg_listener(&config).await?;
+
+    let addr = format!("0.0.0.0:{}", config.server.port);
+    let pool = PgPoolOptions::new().connect(&config.server.db_url).await?;
+    let state = AppState::new(config, pool);
+    let app = get_router(state.clone());
+    let listener = TcpListener::bind(&addr).await?;
+    info!("Listening on: {}", addr);
+
+    tokio::try_join!(
+        async {
+            axum::serve(listener, app.into_make_service())
+                .await
+                .map_err(anyhow::Error::from)
+        },
+        setup_pg_listener(&state.config, state.listener_metrics.clone()),
+        setup_job_worker(&state.config, state.listener_metrics.clone()),
+    )?;
     Ok(())
 }
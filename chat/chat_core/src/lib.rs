@@ -1,6 +1,6 @@
 use std::fmt::Debug;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 pub mod middlewares;
@@ -67,6 +67,35 @@ pub struct ChatUser {
     pub id: i64,
     pub fullname: String,
     pub email: String,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub presence: Presence,
+}
+
+/// Presence of a user, tracked by notify_server connections.
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    sqlx::Type,
+    ToSchema,
+)]
+#[sqlx(type_name = "presence_status", rename_all = "snake_case")]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum Presence {
+    #[serde(alias = "online", alias = "Online")]
+    Online,
+    #[serde(alias = "away", alias = "Away")]
+    Away,
+    #[serde(alias = "offline", alias = "Offline")]
+    #[default]
+    Offline,
 }
 
 #[derive(
@@ -87,7 +116,7 @@ pub enum ChatType {
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow, Clone, PartialEq, ToSchema)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all(serialize = "camelCase"))]
 pub struct Chat {
     pub id: i64,
     #[serde(alias = "wsId")]
@@ -111,10 +140,23 @@ pub struct Message {
     pub content: String,
     pub modified_content: Option<String>,
     pub files: Vec<String>,
+    #[serde(default, alias = "replyTo")]
+    #[sqlx(default)]
+    pub reply_to: Option<i64>,
+    #[serde(default)]
+    #[sqlx(json)]
+    pub citations: Vec<Citation>,
     #[serde(alias = "createdAt")]
     pub created_at: DateTime<Utc>,
 }
 
+/// A source a bot answer is based on.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
+pub struct Citation {
+    /// path of the indexed document
+    pub path: String,
+}
+
 /*
 -- create agent_type type
 CREATE TYPE agent_type AS ENUM ('proxy', 'reply', 'tap');
@@ -177,6 +219,89 @@ pub struct ChatAgent {
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(
+    Debug, Serialize, Deserialize, Default, Clone, PartialEq, PartialOrd, sqlx::Type, ToSchema,
+)]
+#[sqlx(type_name = "indexing_job_status", rename_all = "snake_case")]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum IndexingJobStatus {
+    #[serde(alias = "pending", alias = "Pending")]
+    #[default]
+    Pending,
+    #[serde(alias = "running", alias = "Running")]
+    Running,
+    #[serde(alias = "done", alias = "Done")]
+    Done,
+    #[serde(alias = "failed", alias = "Failed")]
+    Failed,
+}
+
+/// An uploaded file being added to a bot's knowledge base.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexingJob {
+    pub id: i64,
+    #[serde(alias = "botId")]
+    pub bot_id: i64,
+    #[serde(alias = "createdBy")]
+    pub created_by: i64,
+    /// chat file url
+    pub file: String,
+    pub status: IndexingJobStatus,
+    pub error: Option<String>,
+    #[serde(alias = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(alias = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Which messages of a chat notify a user.
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    sqlx::Type,
+    ToSchema,
+)]
+#[sqlx(type_name = "notification_level", rename_all = "snake_case")]
+#[serde(rename_all(serialize = "camelCase"))]
+pub enum NotificationLevel {
+    #[serde(alias = "all", alias = "All")]
+    #[default]
+    All,
+    /// only messages which mention the user
+    #[serde(alias = "mentions", alias = "Mentions")]
+    Mentions,
+    #[serde(alias = "muted", alias = "Muted")]
+    Muted,
+}
+
+/// Notification settings of a user for a chat, or their defaults with `chat_id` 0.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationSettings {
+    #[serde(alias = "userId")]
+    pub user_id: i64,
+    #[serde(alias = "chatId")]
+    pub chat_id: i64,
+    pub level: NotificationLevel,
+    /// do not disturb from `dnd_start` until `dnd_end` utc, may span midnight
+    #[serde(alias = "dndStart")]
+    #[schema(value_type = Option<String>, example = "22:00:00")]
+    pub dnd_start: Option<NaiveTime>,
+    #[serde(alias = "dndEnd")]
+    #[schema(value_type = Option<String>, example = "07:00:00")]
+    pub dnd_end: Option<NaiveTime>,
+    #[serde(alias = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
 impl User {
     pub fn new(id: i64, fullname: &str, email: &str) -> Self {
         Self {
@@ -0,0 +1,239 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    postgres::{PgListener, PgNotification},
+    FromRow, PgPool,
+};
+use tracing::{info, warn};
+
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A Postgres LISTEN connection which reconnects with exponential backoff instead of
+/// ending the stream on the first error.
+pub struct PgNotifications {
+    db_url: String,
+    channels: Vec<String>,
+    listener: Option<PgListener>,
+    backoff: Backoff,
+    metrics: ListenerMetrics,
+}
+
+/// Counters of a [`PgNotifications`], cheap to clone and share with handlers.
+#[derive(Debug, Clone, Default)]
+pub struct ListenerMetrics(Arc<ListenerMetricsInner>);
+
+#[derive(Debug, Default)]
+struct ListenerMetricsInner {
+    received: AtomicU64,
+    reconnects: AtomicU64,
+    errors: AtomicU64,
+    invalid_payloads: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListenerStats {
+    pub received: u64,
+    /// connections lost and re-established
+    pub reconnects: u64,
+    /// errors while receiving or reconnecting
+    pub errors: u64,
+    /// notifications skipped because their payload couldn't be handled
+    pub invalid_payloads: u64,
+}
+
+/// An event logged in `notify_events`. Notifications only carry its id, so they stay
+/// below the 8000 byte payload limit of pg_notify whatever the size of the event.
+#[derive(Debug, Clone, FromRow)]
+pub struct NotifyEvent {
+    pub id: i64,
+    pub channel: String,
+    /// json of the event
+    pub payload: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventRef {
+    event_id: i64,
+}
+
+/// Exponential backoff between reconnect attempts, doubling up to a maximum.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    current: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl PgNotifications {
+    pub async fn connect(db_url: &str, channels: &[&str]) -> Result<Self, sqlx::Error> {
+        let channels: Vec<String> = channels.iter().map(|c| c.to_string()).collect();
+        let listener = listen(db_url, &channels).await?;
+        info!("Listening to {}", channels.join(", "));
+        Ok(Self {
+            db_url: db_url.to_string(),
+            channels,
+            listener: Some(listener),
+            backoff: Backoff::default(),
+            metrics: ListenerMetrics::default(),
+        })
+    }
+
+    /// Record into `metrics`, e.g. counters owned by the server state.
+    pub fn with_metrics(mut self, metrics: ListenerMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn metrics(&self) -> ListenerMetrics {
+        self.metrics.clone()
+    }
+
+    /// Wait for the next notification, reconnecting for as long as needed.
+    pub async fn recv(&mut self) -> PgNotification {
+        loop {
+            if self.listener.is_none() {
+                self.reconnect().await;
+                continue;
+            }
+            let listener = self.listener.as_mut().expect("listener should exist");
+            match listener.try_recv().await {
+                Ok(Some(notif)) => {
+                    self.backoff.reset();
+                    self.metrics.0.received.fetch_add(1, Ordering::Relaxed);
+                    return notif;
+                }
+                Ok(None) => {
+                    // sqlx reconnects and listens again on the next call, notifications
+                    // sent in between are lost
+                    warn!(
+                        "Lost connection to {}, reconnecting",
+                        self.channels.join(", ")
+                    );
+                    self.metrics.0.reconnects.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    warn!("Failed to receive notification: {}", e);
+                    self.metrics.0.errors.fetch_add(1, Ordering::Relaxed);
+                    self.listener = None;
+                }
+            }
+        }
+    }
+
+    async fn reconnect(&mut self) {
+        let delay = self.backoff.next_delay();
+        tokio::time::sleep(delay).await;
+        match listen(&self.db_url, &self.channels).await {
+            Ok(listener) => {
+                info!("Reconnected to {}", self.channels.join(", "));
+                self.metrics.0.reconnects.fetch_add(1, Ordering::Relaxed);
+                self.listener = Some(listener);
+            }
+            Err(e) => {
+                warn!("Failed to reconnect after {:?}: {}", delay, e);
+                self.metrics.0.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl ListenerMetrics {
+    /// Record a notification which was skipped because its payload couldn't be handled.
+    pub fn invalid_payload(&self) {
+        self.0.invalid_payloads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> ListenerStats {
+        ListenerStats {
+            received: self.0.received.load(Ordering::Relaxed),
+            reconnects: self.0.reconnects.load(Ordering::Relaxed),
+            errors: self.0.errors.load(Ordering::Relaxed),
+            invalid_payloads: self.0.invalid_payloads.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl NotifyEvent {
+    /// Id of the event a notification refers to, e.g. `{"event_id":42}`.
+    pub fn id_of(notif: &PgNotification) -> Result<i64, serde_json::Error> {
+        serde_json::from_str::<EventRef>(notif.payload()).map(|v| v.event_id)
+    }
+
+    /// The event a notification refers to, `None` if it was pruned meanwhile.
+    pub async fn fetch(pool: &PgPool, id: i64) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as("SELECT id, channel, payload::text FROM notify_events WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            current: min,
+            min,
+            max,
+        }
+    }
+
+    /// The delay before the next attempt, each call doubles the following one.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.min;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(MIN_BACKOFF, MAX_BACKOFF)
+    }
+}
+
+async fn listen(db_url: &str, channels: &[String]) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect(db_url).await?;
+    listener
+        .listen_all(channels.iter().map(String::as_str))
+        .await?;
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_should_double_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(500));
+        let delays: Vec<_> = (0..5).map(|_| backoff.next_delay().as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn metrics_should_be_shared() {
+        let metrics = ListenerMetrics::default();
+        metrics.clone().invalid_payload();
+        assert_eq!(
+            metrics.stats(),
+            ListenerStats {
+                invalid_payloads: 1,
+                ..Default::default()
+            }
+        );
+    }
+}
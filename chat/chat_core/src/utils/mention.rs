@@ -0,0 +1,30 @@
+/// Whether `content` mentions `@handle`, case insensitive.
+pub fn mentions(content: &str, handle: &str) -> bool {
+    content.split('@').skip(1).any(|s| {
+        let name: String = s
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            .collect();
+        name.trim_end_matches('.').eq_ignore_ascii_case(handle)
+    })
+}
+
+/// Users and bots are mentioned by the local part of their email, e.g. `@tchen` for
+/// `tchen@acme.org`.
+pub fn mention_handle(email: &str) -> String {
+    email.split('@').next().unwrap_or_default().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mentions_should_work() {
+        assert!(mentions("@rust what is a trait?", "rust"));
+        assert!(mentions("hey @Rust.", "rust"));
+        assert!(!mentions("@rustacean hi", "rust"));
+        assert!(!mentions("mail me at a@rust", "python"));
+        assert_eq!(mention_handle("Tchen@acme.org"), "tchen");
+    }
+}
@@ -1,3 +1,7 @@
 mod jwt;
+mod listener;
+mod mention;
 
 pub use jwt::{DecodingKey, EncodingKey};
+pub use listener::{Backoff, ListenerMetrics, ListenerStats, NotifyEvent, PgNotifications};
+pub use mention::{mention_handle, mentions};
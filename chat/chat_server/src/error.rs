@@ -41,6 +41,12 @@ pub enum AppError {
     #[error("{0}")]
     ChatFileError(String),
 
+    #[error("indexing job error: {0}")]
+    IndexingJobError(String),
+
+    #[error("invalid notification settings: {0}")]
+    NotificationSettingsError(String),
+
     #[error("chat does not exist")]
     ChatDoesNotExist,
 
@@ -81,6 +87,8 @@ impl IntoResponse for AppError {
             AppError::IoError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::MessageCreateError(_) => StatusCode::BAD_REQUEST,
             AppError::ChatFileError(_) => StatusCode::BAD_REQUEST,
+            AppError::IndexingJobError(_) => StatusCode::BAD_REQUEST,
+            AppError::NotificationSettingsError(_) => StatusCode::BAD_REQUEST,
             AppError::ChatDoesNotExist => StatusCode::NOT_FOUND,
             AppError::CreateAgentError(_) => StatusCode::BAD_REQUEST,
             AppError::NotChatMemberError { .. } => StatusCode::FORBIDDEN,
@@ -0,0 +1,92 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+
+use crate::{AppError, AppState, CreateIndexingJobs};
+use chat_core::User;
+
+/// Add uploaded files to a bot's knowledge base, they are indexed asynchronously by bot-server.
+#[utoipa::path(
+    post,
+    path = "/api/bots/{id}/documents",
+    params(
+        ("id" = u64, Path, description = "Bot id")
+    ),
+    responses(
+        (status = 202, description = "Indexing jobs created", body = Vec<IndexingJob>),
+        (status = 400, description = "Invalid input", body = ErrorOutput),
+        (status = 404, description = "Bot not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn add_document_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(input): Json<CreateIndexingJobs>,
+) -> Result<impl IntoResponse, AppError> {
+    let jobs = state
+        .create_indexing_jobs(input, id, user.id as _, user.ws_id as _)
+        .await?;
+    Ok((StatusCode::ACCEPTED, Json(jobs)))
+}
+
+/// List the indexing jobs of a bot, latest first.
+#[utoipa::path(
+    get,
+    path = "/api/bots/{id}/documents",
+    params(
+        ("id" = u64, Path, description = "Bot id")
+    ),
+    responses(
+        (status = 200, description = "List of indexing jobs", body = Vec<IndexingJob>),
+        (status = 404, description = "Bot not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn list_document_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let jobs = state.list_indexing_jobs(id, user.ws_id as _).await?;
+    Ok(Json(jobs))
+}
+
+/// Get the status of an indexing job.
+#[utoipa::path(
+    get,
+    path = "/api/bots/{id}/documents/{job_id}",
+    params(
+        ("id" = u64, Path, description = "Bot id"),
+        ("job_id" = u64, Path, description = "Indexing job id")
+    ),
+    responses(
+        (status = 200, description = "Indexing job", body = IndexingJob),
+        (status = 404, description = "Indexing job not found", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn get_document_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path((id, job_id)): Path<(u64, u64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let job = state.get_indexing_job(id, job_id, user.ws_id as _).await?;
+    match job {
+        Some(job) => Ok(Json(job)),
+        None => Err(AppError::NotFound(format!(
+            "indexing job with id {} not found",
+            job_id
+        ))),
+    }
+}
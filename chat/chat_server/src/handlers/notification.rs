@@ -0,0 +1,121 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+
+use crate::{AppError, AppState, UpdateNotificationSettings};
+use chat_core::User;
+
+/// Get the default notification settings of the current user.
+#[utoipa::path(
+    get,
+    path = "/api/settings/notifications",
+    responses(
+        (status = 200, description = "Notification settings", body = NotificationSettings),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn get_notification_settings_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let settings = state.get_notification_settings(user.id as _, 0).await?;
+    Ok(Json(settings))
+}
+
+/// Set the default notification settings of the current user, used for chats without
+/// settings of their own.
+#[utoipa::path(
+    put,
+    path = "/api/settings/notifications",
+    responses(
+        (status = 200, description = "Notification settings updated", body = NotificationSettings),
+        (status = 400, description = "Invalid input", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn update_notification_settings_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Json(input): Json<UpdateNotificationSettings>,
+) -> Result<impl IntoResponse, AppError> {
+    let settings = state
+        .update_notification_settings(input, user.id as _, 0)
+        .await?;
+    Ok(Json(settings))
+}
+
+/// Get the notification settings of the current user for a chat, `chatId` is 0 if
+/// the chat uses the defaults.
+#[utoipa::path(
+    get,
+    path = "/api/chats/{id}/notifications",
+    params(("id"=u64, Path, description="Chat ID")),
+    responses(
+        (status = 200, description = "Notification settings", body = NotificationSettings),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn get_chat_notification_settings_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    let settings = state.get_notification_settings(user.id as _, id).await?;
+    Ok(Json(settings))
+}
+
+/// Set the notification settings of the current user for a chat, e.g. to mute it.
+#[utoipa::path(
+    put,
+    path = "/api/chats/{id}/notifications",
+    params(("id"=u64, Path, description="Chat ID")),
+    responses(
+        (status = 200, description = "Notification settings updated", body = NotificationSettings),
+        (status = 400, description = "Invalid input", body = ErrorOutput),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn update_chat_notification_settings_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+    Json(input): Json<UpdateNotificationSettings>,
+) -> Result<impl IntoResponse, AppError> {
+    let settings = state
+        .update_notification_settings(input, user.id as _, id)
+        .await?;
+    Ok(Json(settings))
+}
+
+/// Remove the notification settings of the current user for a chat, it uses the
+/// defaults again.
+#[utoipa::path(
+    delete,
+    path = "/api/chats/{id}/notifications",
+    params(("id"=u64, Path, description="Chat ID")),
+    responses(
+        (status = 204, description = "Notification settings removed"),
+    ),
+    security(
+        ("token" = [])
+    )
+)]
+pub(crate) async fn delete_chat_notification_settings_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, AppError> {
+    state.delete_notification_settings(user.id as _, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
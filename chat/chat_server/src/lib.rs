@@ -56,6 +56,12 @@ pub async fn get_router(state: AppState) -> Result<Router, AppError> {
                 .patch(update_agent_handler),
         )
         .route("/:id/messages", get(list_message_handler))
+        .route(
+            "/:id/notifications",
+            get(get_chat_notification_settings_handler)
+                .put(update_chat_notification_settings_handler)
+                .delete(delete_chat_notification_settings_handler),
+        )
         .layer(from_fn_with_state(state.clone(), verify_chat))
         .route("/", get(list_chat_handler).post(create_chat_handler));
 
@@ -72,8 +78,17 @@ pub async fn get_router(state: AppState) -> Result<Router, AppError> {
 
     let api = Router::new()
         .route("/users", get(list_chat_users_handler))
+        .route(
+            "/settings/notifications",
+            get(get_notification_settings_handler).put(update_notification_settings_handler),
+        )
         .nest("/chats", chat)
         .route("/upload", post(upload_handler))
+        .route(
+            "/bots/:id/documents",
+            get(list_document_handler).post(add_document_handler),
+        )
+        .route("/bots/:id/documents/:job_id", get(get_document_handler))
         .route("/files/:ws_id/*path", get(file_handler))
         .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
         .route("/signin", post(signin_handler))
@@ -0,0 +1,191 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{AppError, AppState, ChatFile};
+use chat_core::IndexingJob;
+
+/// Extensions of documents a bot's knowledge base can be built from
+const DOCUMENT_EXTENSIONS: [&str; 5] = ["md", "markdown", "txt", "text", "pdf"];
+
+/// Add uploaded files to a bot's knowledge base
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateIndexingJobs {
+    /// Urls of files returned by the upload api
+    pub files: Vec<String>,
+}
+
+#[allow(dead_code)]
+impl AppState {
+    pub async fn create_indexing_jobs(
+        &self,
+        input: CreateIndexingJobs,
+        bot_id: u64,
+        user_id: u64,
+        ws_id: u64,
+    ) -> Result<Vec<IndexingJob>, AppError> {
+        self.verify_bot(bot_id, ws_id).await?;
+        if input.files.is_empty() {
+            return Err(AppError::IndexingJobError("files are empty".to_string()));
+        }
+        // verify files - should exist, belong to the workspace and be a supported document
+        let base_dir = &self.config.server.base_dir;
+        for s in &input.files {
+            let file = ChatFile::from_str(s)?;
+            if file.ws_id != ws_id || !file.path(base_dir).exists() {
+                return Err(AppError::IndexingJobError(format!(
+                    "File does not exist: {}",
+                    s
+                )));
+            }
+            if !DOCUMENT_EXTENSIONS.contains(&file.ext.to_lowercase().as_str()) {
+                return Err(AppError::IndexingJobError(format!(
+                    "Unsupported document type: {}",
+                    file.ext
+                )));
+            }
+        }
+
+        let jobs = sqlx::query_as(
+            r#"
+            INSERT INTO indexing_jobs (bot_id, created_by, file)
+            SELECT $1, $2, file FROM UNNEST($3::varchar[]) AS file
+            RETURNING *
+            "#,
+        )
+        .bind(bot_id as i64)
+        .bind(user_id as i64)
+        .bind(&input.files)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(jobs)
+    }
+
+    pub async fn list_indexing_jobs(
+        &self,
+        bot_id: u64,
+        ws_id: u64,
+    ) -> Result<Vec<IndexingJob>, AppError> {
+        self.verify_bot(bot_id, ws_id).await?;
+        let jobs = sqlx::query_as(
+            r#"
+            SELECT * FROM indexing_jobs
+            WHERE bot_id = $1
+            ORDER BY id DESC
+            "#,
+        )
+        .bind(bot_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(jobs)
+    }
+
+    pub async fn get_indexing_job(
+        &self,
+        bot_id: u64,
+        job_id: u64,
+        ws_id: u64,
+    ) -> Result<Option<IndexingJob>, AppError> {
+        self.verify_bot(bot_id, ws_id).await?;
+        let job = sqlx::query_as(
+            r#"
+            SELECT * FROM indexing_jobs
+            WHERE id = $1 AND bot_id = $2
+            "#,
+        )
+        .bind(job_id as i64)
+        .bind(bot_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(job)
+    }
+
+    /// Verify the user is a bot of the workspace
+    async fn verify_bot(&self, bot_id: u64, ws_id: u64) -> Result<(), AppError> {
+        let bot: Option<(i64,)> =
+            sqlx::query_as("SELECT id FROM users WHERE id = $1 AND ws_id = $2 AND is_bot = TRUE")
+                .bind(bot_id as i64)
+                .bind(ws_id as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+        match bot {
+            Some(_) => Ok(()),
+            None => Err(AppError::NotFound(format!(
+                "bot with id {} not found",
+                bot_id
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use chat_core::IndexingJobStatus;
+
+    use super::*;
+    use crate::CreateUser;
+
+    #[tokio::test]
+    async fn create_and_list_indexing_jobs_should_work() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateUser::new("acme", "docs", "docs@bot.org", "zhouzhangqi");
+        let bot = state.create_user(&input).await?;
+
+        let url = upload_dummy_file(&state, "readme.md")?;
+        let input = CreateIndexingJobs { files: vec![url] };
+        let jobs = state
+            .create_indexing_jobs(input, bot.id as _, 1, bot.ws_id as _)
+            .await?;
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].status, IndexingJobStatus::Pending);
+
+        let list = state
+            .list_indexing_jobs(bot.id as _, bot.ws_id as _)
+            .await?;
+        assert_eq!(list, jobs);
+
+        let job = state
+            .get_indexing_job(bot.id as _, jobs[0].id as _, bot.ws_id as _)
+            .await?;
+        assert_eq!(job.as_ref(), jobs.first());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_indexing_jobs_should_reject_invalid_input() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = CreateUser::new("acme", "docs", "docs@bot.org", "zhouzhangqi");
+        let bot = state.create_user(&input).await?;
+
+        // unsupported document type
+        let url = upload_dummy_file(&state, "logo.png")?;
+        let input = CreateIndexingJobs { files: vec![url] };
+        let err = state
+            .create_indexing_jobs(input, bot.id as _, 1, bot.ws_id as _)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "indexing job error: Unsupported document type: png"
+        );
+
+        // user 2 is not a bot
+        let input = CreateIndexingJobs { files: vec![] };
+        let err = state
+            .create_indexing_jobs(input, 2, 1, bot.ws_id as _)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Not found: bot with id 2 not found");
+        Ok(())
+    }
+
+    fn upload_dummy_file(state: &AppState, name: &str) -> Result<String> {
+        let file = ChatFile::new(1, name, b"# hello world");
+        let path = file.path(&state.config.server.base_dir);
+        std::fs::create_dir_all(path.parent().expect("file path parent should exists"))?;
+        std::fs::write(&path, b"# hello world")?;
+        Ok(file.url())
+    }
+}
@@ -11,6 +11,9 @@ pub struct CreateMessage {
     pub content: String,
     #[serde(default)]
     pub files: Vec<String>,
+    /// id of the message in the same chat this message replies to
+    #[serde(default)]
+    pub reply_to: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema, IntoParams)]
@@ -45,6 +48,21 @@ impl AppState {
             }
         }
 
+        // verify reply_to - should be a message of the same chat
+        if let Some(reply_to) = input.reply_to {
+            let replied: Option<(i64,)> =
+                sqlx::query_as("SELECT chat_id FROM messages WHERE id = $1")
+                    .bind(reply_to as i64)
+                    .fetch_optional(&self.pool)
+                    .await?;
+            if replied.map(|(id,)| id) != Some(chat_id as i64) {
+                return Err(AppError::MessageCreateError(format!(
+                    "Replied message does not exist: {}",
+                    reply_to
+                )));
+            }
+        }
+
         let mut agents = self.list_agents(chat_id).await?;
         let decision = if let Some(agent) = agents.pop() {
             let agent = AgentVariant::from(agent);
@@ -67,8 +85,8 @@ impl AppState {
 
         let message: Message = sqlx::query_as(
             r#"
-        INSERT INTO messages (chat_id, sender_id, content, modified_content, files)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO messages (chat_id, sender_id, content, modified_content, files, reply_to)
+        VALUES ($1, $2, $3, $4, $5, $6)
         RETURNING *
         "#,
         )
@@ -77,6 +95,7 @@ impl AppState {
         .bind(input.content)
         .bind(modified_content)
         .bind(&input.files)
+        .bind(input.reply_to.map(|id| id as i64))
         .fetch_one(&self.pool)
         .await?;
 
@@ -124,7 +143,7 @@ impl AppState {
         };
         let messages = sqlx::query_as(
             r#"
-        SELECT id, chat_id, sender_id, content, modified_content, files, created_at
+        SELECT id, chat_id, sender_id, content, modified_content, files, reply_to, citations, created_at
         FROM messages
         WHERE chat_id = $1
         AND id < $2
@@ -158,6 +177,7 @@ mod tests {
         let input = CreateMessage {
             content: "Hello".to_string(),
             files: vec![],
+            reply_to: None,
         };
         let message = state.create_message(input, chat.id as _, 1).await?;
         assert_eq!(message.content, "Hello");
@@ -166,6 +186,7 @@ mod tests {
         let input = CreateMessage {
             content: "Hello".to_string(),
             files: vec!["1".to_string()],
+            reply_to: None,
         };
         let err = state
             .create_message(input, chat.id as _, 1)
@@ -178,10 +199,34 @@ mod tests {
         let input = CreateMessage {
             content: "Hello".to_string(),
             files: vec![url],
+            reply_to: None,
         };
         let message = state.create_message(input, chat.id as _, 1).await?;
         assert_eq!(message.content, "Hello");
         assert_eq!(message.files.len(), 1);
+
+        // replies should reference a message of the same chat
+        let input = CreateMessage {
+            content: "Hi".to_string(),
+            files: vec![],
+            reply_to: Some(message.id as _),
+        };
+        let reply = state.create_message(input, chat.id as _, 2).await?;
+        assert_eq!(reply.reply_to, Some(message.id));
+
+        let input = CreateMessage {
+            content: "Hi".to_string(),
+            files: vec![],
+            reply_to: Some(1),
+        };
+        let err = state
+            .create_message(input, chat.id as _, 2)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "message create error: Replied message does not exist: 1"
+        );
         Ok(())
     }
 
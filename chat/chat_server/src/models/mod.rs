@@ -1,13 +1,17 @@
 mod agent;
 mod chat;
 mod file;
+mod indexing;
 mod messages;
+mod notification;
 mod user;
 mod workspace;
 
 pub use agent::*;
 pub use chat::*;
+pub use indexing::*;
 pub use messages::*;
+pub use notification::*;
 use serde::{Deserialize, Serialize};
 pub use user::{CreateUser, SigninUser};
 
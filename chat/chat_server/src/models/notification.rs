@@ -0,0 +1,182 @@
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{AppError, AppState};
+use chat_core::{NotificationLevel, NotificationSettings};
+
+/// Notification settings, for a chat or by default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateNotificationSettings {
+    #[serde(default)]
+    pub level: NotificationLevel,
+    /// do not disturb from `dndStart` until `dndEnd` utc, both or neither are set
+    #[serde(default, alias = "dnd_start")]
+    #[schema(value_type = Option<String>, example = "22:00:00")]
+    pub dnd_start: Option<NaiveTime>,
+    #[serde(default, alias = "dnd_end")]
+    #[schema(value_type = Option<String>, example = "07:00:00")]
+    pub dnd_end: Option<NaiveTime>,
+}
+
+impl UpdateNotificationSettings {
+    pub fn new(level: NotificationLevel, dnd: Option<(NaiveTime, NaiveTime)>) -> Self {
+        Self {
+            level,
+            dnd_start: dnd.map(|(start, _)| start),
+            dnd_end: dnd.map(|(_, end)| end),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl AppState {
+    /// Settings of a user for a chat, or their defaults with `chat_id` 0.
+    ///
+    /// A chat without settings of its own gets the defaults, with `chat_id` 0.
+    pub async fn get_notification_settings(
+        &self,
+        user_id: u64,
+        chat_id: u64,
+    ) -> Result<NotificationSettings, AppError> {
+        let settings: Option<NotificationSettings> = sqlx::query_as(
+            r#"
+            SELECT user_id, chat_id, level, dnd_start, dnd_end, updated_at
+            FROM notification_settings
+            WHERE user_id = $1 AND chat_id IN (0, $2)
+            ORDER BY chat_id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(user_id as i64)
+        .bind(chat_id as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(settings.unwrap_or_else(|| NotificationSettings {
+            user_id: user_id as _,
+            chat_id: 0,
+            level: NotificationLevel::All,
+            dnd_start: None,
+            dnd_end: None,
+            updated_at: chrono::Utc::now(),
+        }))
+    }
+
+    pub async fn update_notification_settings(
+        &self,
+        input: UpdateNotificationSettings,
+        user_id: u64,
+        chat_id: u64,
+    ) -> Result<NotificationSettings, AppError> {
+        if input.dnd_start.is_some() != input.dnd_end.is_some() {
+            return Err(AppError::NotificationSettingsError(
+                "dndStart and dndEnd must be set together".to_string(),
+            ));
+        }
+        let settings = sqlx::query_as(
+            r#"
+            INSERT INTO notification_settings (user_id, chat_id, level, dnd_start, dnd_end)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (user_id, chat_id) DO UPDATE
+            SET level = $3, dnd_start = $4, dnd_end = $5, updated_at = CURRENT_TIMESTAMP
+            RETURNING user_id, chat_id, level, dnd_start, dnd_end, updated_at
+            "#,
+        )
+        .bind(user_id as i64)
+        .bind(chat_id as i64)
+        .bind(input.level)
+        .bind(input.dnd_start)
+        .bind(input.dnd_end)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(settings)
+    }
+
+    /// Remove the settings of a user for a chat, which falls back to their defaults.
+    pub async fn delete_notification_settings(
+        &self,
+        user_id: u64,
+        chat_id: u64,
+    ) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM notification_settings WHERE user_id = $1 AND chat_id = $2")
+            .bind(user_id as i64)
+            .bind(chat_id as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn update_notification_settings_should_use_camel_case() -> Result<()> {
+        let input: UpdateNotificationSettings = serde_json::from_str(
+            r#"{"level":"mentions","dndStart":"22:00:00","dndEnd":"07:00:00"}"#,
+        )?;
+        assert_eq!(input.level, NotificationLevel::Mentions);
+        assert_eq!(input.dnd_start, NaiveTime::from_hms_opt(22, 0, 0));
+        assert_eq!(input.dnd_end, NaiveTime::from_hms_opt(7, 0, 0));
+        assert!(serde_json::to_string(&input)?.contains(r#""dndStart":"22:00:00""#));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn notification_settings_should_fall_back_to_defaults() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let settings = state.get_notification_settings(1, 1).await?;
+        assert_eq!(
+            (settings.chat_id, settings.level),
+            (0, NotificationLevel::All)
+        );
+
+        let night = (
+            NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+        );
+        let input = UpdateNotificationSettings::new(NotificationLevel::Mentions, Some(night));
+        state.update_notification_settings(input, 1, 0).await?;
+        let input = UpdateNotificationSettings::new(NotificationLevel::Muted, None);
+        state.update_notification_settings(input, 1, 1).await?;
+
+        let settings = state.get_notification_settings(1, 1).await?;
+        assert_eq!(
+            (settings.chat_id, settings.level),
+            (1, NotificationLevel::Muted)
+        );
+        let settings = state.get_notification_settings(1, 2).await?;
+        assert_eq!(
+            (settings.chat_id, settings.level),
+            (0, NotificationLevel::Mentions)
+        );
+        assert_eq!(settings.dnd_start, Some(night.0));
+
+        state.delete_notification_settings(1, 1).await?;
+        let settings = state.get_notification_settings(1, 1).await?;
+        assert_eq!(settings.chat_id, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn notification_settings_should_reject_half_dnd_schedule() -> Result<()> {
+        let (_tdb, state) = AppState::new_for_test().await?;
+        let input = UpdateNotificationSettings {
+            dnd_start: NaiveTime::from_hms_opt(22, 0, 0),
+            ..Default::default()
+        };
+        let err = state
+            .update_notification_settings(input, 1, 0)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid notification settings: dndStart and dndEnd must be set together"
+        );
+        Ok(())
+    }
+}
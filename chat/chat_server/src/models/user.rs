@@ -126,11 +126,18 @@ impl AppState {
         Ok(users)
     }
     pub async fn fetch_chat_users(&self, ws_id: u64) -> Result<Vec<ChatUser>, AppError> {
-        let users =
-            sqlx::query_as("select id, fullname, email from users where ws_id = $1 order by id")
-                .bind(ws_id as i64)
-                .fetch_all(&self.pool)
-                .await?;
+        let users = sqlx::query_as(
+            r#"
+            SELECT u.id, u.fullname, u.email, COALESCE(p.status, 'offline') AS presence
+            FROM users u
+            LEFT JOIN user_presence p ON p.user_id = u.id
+            WHERE u.ws_id = $1
+            ORDER BY u.id
+            "#,
+        )
+        .bind(ws_id as i64)
+        .fetch_all(&self.pool)
+        .await?;
         Ok(users)
     }
 }
@@ -54,6 +54,7 @@ impl AppState {
 mod tests {
 
     use crate::models::CreateUser;
+    use chat_core::Presence;
 
     use super::*;
     #[tokio::test]
@@ -87,9 +88,14 @@ mod tests {
     async fn workspace_should_fetch_all_chat_users() -> anyhow::Result<()> {
         let (_tdb, state) = AppState::new_for_test().await?;
 
+        sqlx::query("INSERT INTO user_presence (user_id, status) VALUES (1, 'away')")
+            .execute(&state.pool)
+            .await?;
         let users = state.fetch_chat_users(1).await?;
 
         assert_eq!(users.len(), 5);
+        assert_eq!(users[0].presence, Presence::Away);
+        assert_eq!(users[1].presence, Presence::Offline);
         Ok(())
     }
 }
@@ -1,5 +1,8 @@
 use axum::Router;
-use chat_core::{AgentType, Chat, ChatAgent, ChatType, ChatUser, Message, User, Workspace};
+use chat_core::{
+    AgentType, Chat, ChatAgent, ChatType, ChatUser, Citation, IndexingJob, IndexingJobStatus,
+    Message, NotificationLevel, NotificationSettings, Presence, User, Workspace,
+};
 use utoipa::{
     openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
     Modify, OpenApi,
@@ -9,7 +12,8 @@ use utoipa_redoc::{Redoc, Servable};
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::{
-    handlers::*, CreateChat, CreateMessage, CreateUser, ListMessages, SigninUser, UpdateAgent,
+    handlers::*, CreateChat, CreateIndexingJobs, CreateMessage, CreateUser, ListMessages,
+    SigninUser, UpdateAgent, UpdateNotificationSettings,
 };
 use crate::{AppState, ErrorOutput};
 
@@ -30,9 +34,19 @@ pub(crate) trait OpenApiRouter {
         create_chat_handler,
         get_chat_handler,
         list_message_handler,
+        add_document_handler,
+        list_document_handler,
+        get_document_handler,
+        get_notification_settings_handler,
+        update_notification_settings_handler,
+        get_chat_notification_settings_handler,
+        update_chat_notification_settings_handler,
+        delete_chat_notification_settings_handler,
     ),
-    components(schemas(User, Chat, ChatType, ChatUser, Message, Workspace,
-        SigninUser, CreateUser, AuthOutput, ErrorOutput, CreateChat, CreateMessage, ListMessages, ChatAgent, UpdateAgent, AgentType)),
+    components(schemas(User, Chat, ChatType, ChatUser, Message, Citation, Workspace,
+        SigninUser, CreateUser, AuthOutput, ErrorOutput, CreateChat, CreateMessage, ListMessages, ChatAgent, UpdateAgent, AgentType,
+        CreateIndexingJobs, IndexingJob, IndexingJobStatus, Presence,
+        NotificationLevel, NotificationSettings, UpdateNotificationSettings)),
     modifiers(&SecurityAddon),
     tags((name="chat", description="Chat operations")),
 )]
@@ -0,0 +1,30 @@
+-- if a user is added, removed or its is_bot flag changed, notify with the user id
+CREATE OR REPLACE FUNCTION user_updated()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM
+            pg_notify('user_updated', json_build_object(
+                'op', TG_OP,
+                'id', OLD.id,
+                'is_bot', OLD.is_bot
+            )::text);
+        RETURN OLD;
+    END IF;
+    IF TG_OP = 'INSERT' OR OLD.is_bot IS DISTINCT FROM NEW.is_bot THEN
+        RAISE NOTICE 'user_updated: %', NEW.id;
+        PERFORM
+            pg_notify('user_updated', json_build_object(
+                'op', TG_OP,
+                'id', NEW.id,
+                'is_bot', NEW.is_bot
+            )::text);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER user_updated_trigger
+AFTER INSERT OR UPDATE OR DELETE ON users
+FOR EACH ROW
+EXECUTE FUNCTION user_updated();
@@ -0,0 +1,12 @@
+-- per bot configuration, bots without a row use the defaults below
+CREATE TABLE IF NOT EXISTS bot_configs (
+    bot_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    -- pgvector table holding the bot's knowledge base
+    knowledge_table VARCHAR(63) NOT NULL DEFAULT 'swiftide_rag' CHECK (knowledge_table ~ '^[a-z_][a-z0-9_]*$'),
+    prompt_model VARCHAR NOT NULL DEFAULT 'llama3.2',
+    embed_model VARCHAR NOT NULL DEFAULT 'nomic-embed-text',
+    system_prompt TEXT,
+    top_k INT NOT NULL DEFAULT 5 CHECK (top_k > 0),
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,33 @@
+-- messages can reply to an earlier message in the same chat
+ALTER TABLE
+    messages
+ADD
+    COLUMN reply_to BIGINT REFERENCES messages(id);
+
+-- include the email in user notifications, bots are mentioned by its local part
+CREATE OR REPLACE FUNCTION user_updated()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM
+            pg_notify('user_updated', json_build_object(
+                'op', TG_OP,
+                'id', OLD.id,
+                'email', OLD.email,
+                'is_bot', OLD.is_bot
+            )::text);
+        RETURN OLD;
+    END IF;
+    IF TG_OP = 'INSERT' OR OLD.is_bot IS DISTINCT FROM NEW.is_bot OR OLD.email IS DISTINCT FROM NEW.email THEN
+        RAISE NOTICE 'user_updated: %', NEW.id;
+        PERFORM
+            pg_notify('user_updated', json_build_object(
+                'op', TG_OP,
+                'id', NEW.id,
+                'email', NEW.email,
+                'is_bot', NEW.is_bot
+            )::text);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
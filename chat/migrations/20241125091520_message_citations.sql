@@ -0,0 +1,5 @@
+-- sources a bot answer is based on, e.g. [{"path": "src/lib.rs"}]
+ALTER TABLE
+    messages
+ADD
+    COLUMN citations JSONB NOT NULL DEFAULT '[]';
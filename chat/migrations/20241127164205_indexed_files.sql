@@ -0,0 +1,9 @@
+-- content hash of every file indexed into a knowledge table, used for incremental indexing
+CREATE TABLE IF NOT EXISTS indexed_files (
+    knowledge_table VARCHAR(63) NOT NULL,
+    path VARCHAR NOT NULL,
+    -- sha1 of the file content
+    hash CHAR(40) NOT NULL,
+    indexed_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (knowledge_table, path)
+);
@@ -0,0 +1,33 @@
+-- create indexing_job_status type
+CREATE TYPE indexing_job_status AS ENUM ('pending', 'running', 'done', 'failed');
+
+-- uploaded files to add to a bot's knowledge base, processed by bot-server
+CREATE TABLE IF NOT EXISTS indexing_jobs (
+    id BIGSERIAL PRIMARY KEY,
+    bot_id BIGINT NOT NULL REFERENCES users(id),
+    created_by BIGINT NOT NULL REFERENCES users(id),
+    -- chat file url, e.g. /files/1/abc/def/0123456789.md
+    file VARCHAR NOT NULL,
+    status indexing_job_status NOT NULL DEFAULT 'pending',
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS indexing_jobs_bot_id_index ON indexing_jobs(bot_id, id DESC);
+
+-- if a job is added, notify bot-server with the job id
+CREATE OR REPLACE FUNCTION add_to_indexing_jobs()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE NOTICE 'add_to_indexing_jobs: %', NEW.id;
+    PERFORM
+        pg_notify('indexing_job_added', NEW.id::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER add_to_indexing_jobs_trigger
+AFTER INSERT ON indexing_jobs
+FOR EACH ROW
+EXECUTE FUNCTION add_to_indexing_jobs();
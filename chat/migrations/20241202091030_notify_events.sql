@@ -0,0 +1,56 @@
+-- log of the events notify_server delivers, so reconnecting clients can replay what they missed
+CREATE TABLE IF NOT EXISTS notify_events (
+    id BIGSERIAL PRIMARY KEY,
+    channel VARCHAR(64) NOT NULL,
+    payload JSONB NOT NULL,
+    -- users the event may concern
+    user_ids BIGINT [] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS notify_events_user_ids_index ON notify_events USING GIN(user_ids);
+
+CREATE INDEX IF NOT EXISTS notify_events_created_at_index ON notify_events(created_at);
+
+-- if chat changed, log the event and notify with chat data and the event id
+CREATE OR REPLACE FUNCTION add_to_chat()
+RETURNS TRIGGER AS $$
+DECLARE
+    PAYLOAD jsonb;
+    USERS bigint[];
+    EVENT_ID bigint;
+BEGIN
+    RAISE NOTICE 'add_to_chat: %', NEW;
+    PAYLOAD := jsonb_build_object('op', TG_OP, 'old', OLD, 'new', NEW);
+    SELECT COALESCE(array_agg(DISTINCT id), '{}') INTO USERS
+    FROM unnest(COALESCE(OLD.members, '{}') || COALESCE(NEW.members, '{}')) AS id;
+    INSERT INTO notify_events (channel, payload, user_ids)
+    VALUES ('chat_updated', PAYLOAD, USERS)
+    RETURNING id INTO EVENT_ID;
+    PERFORM
+        pg_notify('chat_updated', (PAYLOAD || jsonb_build_object('event_id', EVENT_ID))::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- if new message added, log the event and notify with message data and the event id
+CREATE OR REPLACE FUNCTION add_to_message()
+RETURNS TRIGGER AS $$
+DECLARE
+    PAYLOAD jsonb;
+    USERS bigint[];
+    EVENT_ID bigint;
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        RAISE NOTICE 'add_to_message: %', NEW;
+        SELECT members INTO USERS FROM chats WHERE id = NEW.chat_id;
+        PAYLOAD := jsonb_build_object('message', NEW, 'members', USERS);
+        INSERT INTO notify_events (channel, payload, user_ids)
+        VALUES ('chat_message_added', PAYLOAD, USERS)
+        RETURNING id INTO EVENT_ID;
+        PERFORM
+            pg_notify('chat_message_added', (PAYLOAD || jsonb_build_object('event_id', EVENT_ID))::text);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,8 @@
+-- the last message each member has read in a chat, acked by clients over notify_server's websocket
+CREATE TABLE IF NOT EXISTS chat_reads (
+    chat_id BIGINT NOT NULL REFERENCES chats(id),
+    user_id BIGINT NOT NULL REFERENCES users(id),
+    message_id BIGINT NOT NULL REFERENCES messages(id),
+    read_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (chat_id, user_id)
+);
@@ -0,0 +1,9 @@
+-- create presence_status type
+CREATE TYPE presence_status AS ENUM ('online', 'away', 'offline');
+
+-- current presence of users, maintained by notify_server from its connections
+CREATE TABLE IF NOT EXISTS user_presence (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id),
+    status presence_status NOT NULL DEFAULT 'offline',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
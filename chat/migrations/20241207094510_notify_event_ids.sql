@@ -0,0 +1,42 @@
+-- notify with the event id only, the payload is read back from notify_events. pg_notify
+-- payloads are limited to 8000 bytes, which a long message or a large member list exceeds
+CREATE OR REPLACE FUNCTION add_to_chat()
+RETURNS TRIGGER AS $$
+DECLARE
+    PAYLOAD jsonb;
+    USERS bigint[];
+    EVENT_ID bigint;
+BEGIN
+    RAISE NOTICE 'add_to_chat: %', NEW;
+    PAYLOAD := jsonb_build_object('op', TG_OP, 'old', OLD, 'new', NEW);
+    SELECT COALESCE(array_agg(DISTINCT id), '{}') INTO USERS
+    FROM unnest(COALESCE(OLD.members, '{}') || COALESCE(NEW.members, '{}')) AS id;
+    INSERT INTO notify_events (channel, payload, user_ids)
+    VALUES ('chat_updated', PAYLOAD, USERS)
+    RETURNING id INTO EVENT_ID;
+    PERFORM
+        pg_notify('chat_updated', jsonb_build_object('event_id', EVENT_ID)::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION add_to_message()
+RETURNS TRIGGER AS $$
+DECLARE
+    PAYLOAD jsonb;
+    USERS bigint[];
+    EVENT_ID bigint;
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        RAISE NOTICE 'add_to_message: %', NEW.id;
+        SELECT members INTO USERS FROM chats WHERE id = NEW.chat_id;
+        PAYLOAD := jsonb_build_object('message', NEW, 'members', USERS);
+        INSERT INTO notify_events (channel, payload, user_ids)
+        VALUES ('chat_message_added', PAYLOAD, USERS)
+        RETURNING id INTO EVENT_ID;
+        PERFORM
+            pg_notify('chat_message_added', jsonb_build_object('event_id', EVENT_ID)::text);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,15 @@
+-- which messages of a chat notify a user
+CREATE TYPE notification_level AS ENUM ('all', 'mentions', 'muted');
+
+-- notification settings of users per chat, chat_id 0 holds the defaults of a user
+CREATE TABLE IF NOT EXISTS notification_settings (
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    chat_id BIGINT NOT NULL DEFAULT 0,
+    level notification_level NOT NULL DEFAULT 'all',
+    -- do not disturb schedule in utc, may span midnight, e.g. 22:00 to 07:00
+    dnd_start TIME,
+    dnd_end TIME,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, chat_id),
+    CHECK ((dnd_start IS NULL) = (dnd_end IS NULL))
+);
@@ -0,0 +1,70 @@
+-- notify_events ids were taken when an event was logged, but notifications are sent at
+-- commit. With concurrent writers a lower id could be committed after a higher one, and
+-- clients resuming after the higher id would never get it. Events are now staged, and
+-- logged at commit one transaction at a time, so their ids follow the commit order.
+CREATE TABLE IF NOT EXISTS notify_events_pending (
+    id BIGSERIAL PRIMARY KEY,
+    channel VARCHAR(64) NOT NULL,
+    payload JSONB NOT NULL,
+    user_ids BIGINT [] NOT NULL
+);
+
+-- log a staged event and notify with its id, runs when the transaction commits
+CREATE OR REPLACE FUNCTION log_notify_event()
+RETURNS TRIGGER AS $$
+DECLARE
+    EVENT_ID bigint;
+BEGIN
+    -- held until the commit, the next transaction takes its ids after this one committed.
+    -- The lock is global on purpose: clients resume from a single id over all channels
+    -- and chats, so every transaction logging events is serialized, not only those of
+    -- the same chat. Only the commit of such transactions waits, other writes don't.
+    PERFORM pg_advisory_xact_lock(hashtext('notify_events'));
+    DELETE FROM notify_events_pending WHERE id = NEW.id;
+    INSERT INTO notify_events (channel, payload, user_ids)
+    VALUES (NEW.channel, NEW.payload, NEW.user_ids)
+    RETURNING id INTO EVENT_ID;
+    PERFORM
+        pg_notify(NEW.channel, jsonb_build_object('event_id', EVENT_ID)::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE CONSTRAINT TRIGGER notify_events_pending_trigger
+    AFTER INSERT ON notify_events_pending
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW
+    EXECUTE FUNCTION log_notify_event();
+
+CREATE OR REPLACE FUNCTION add_to_chat()
+RETURNS TRIGGER AS $$
+DECLARE
+    PAYLOAD jsonb;
+    USERS bigint[];
+BEGIN
+    RAISE NOTICE 'add_to_chat: %', NEW;
+    PAYLOAD := jsonb_build_object('op', TG_OP, 'old', OLD, 'new', NEW);
+    SELECT COALESCE(array_agg(DISTINCT id), '{}') INTO USERS
+    FROM unnest(COALESCE(OLD.members, '{}') || COALESCE(NEW.members, '{}')) AS id;
+    INSERT INTO notify_events_pending (channel, payload, user_ids)
+    VALUES ('chat_updated', PAYLOAD, USERS);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION add_to_message()
+RETURNS TRIGGER AS $$
+DECLARE
+    PAYLOAD jsonb;
+    USERS bigint[];
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        RAISE NOTICE 'add_to_message: %', NEW.id;
+        SELECT members INTO USERS FROM chats WHERE id = NEW.chat_id;
+        PAYLOAD := jsonb_build_object('message', NEW, 'members', USERS);
+        INSERT INTO notify_events_pending (channel, payload, user_ids)
+        VALUES ('chat_message_added', PAYLOAD, USERS);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
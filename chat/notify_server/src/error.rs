@@ -17,7 +17,10 @@ pub enum AppError {
     IoError(#[from] std::io::Error),
 
     #[error("jwt error: {0}")]
-    JwtError(#[from] jwt_simple::Error),
+    JwtError(jwt_simple::Error),
+
+    #[error("{0}")]
+    AnyError(#[from] anyhow::Error),
 }
 
 impl ErrorOutput {
@@ -33,7 +36,7 @@ impl IntoResponse for AppError {
         let status = match self {
             AppError::JwtError(_) => StatusCode::FORBIDDEN,
 
-            AppError::IoError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::IoError(_) | AppError::AnyError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
         (status, Json(json!(ErrorOutput::new(self.to_string())))).into_response()
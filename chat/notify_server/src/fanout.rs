@@ -0,0 +1,260 @@
+use std::sync::Arc;
+
+use chat_core::{Backoff, PgNotifications, Presence};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{presence::HEARTBEAT_INTERVAL, AppEvent, AppState, LoggedEvent};
+
+/// Postgres channel the replicas exchange ephemeral events on.
+const FANOUT_CHANNEL: &str = "notify_fanout";
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Delivers ephemeral events, which are not logged in Postgres, to every notify_server
+/// replica, the one publishing included.
+pub trait FanOut: Send + Sync + 'static {
+    fn publish(&self, msg: FanOutMessage) -> BoxFuture<'_, anyhow::Result<()>>;
+
+    /// Messages published by any replica from now on.
+    fn subscribe(&self) -> broadcast::Receiver<FanOutMessage>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FanOutMessage {
+    /// replica which published the message
+    pub origin: Uuid,
+    pub body: FanOutBody,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum FanOutBody {
+    /// Send an event to the users connected to each replica.
+    Deliver { user_ids: Vec<u64>, event: AppEvent },
+    /// Presence of a user among the connections of the origin replica, `seq` orders
+    /// the announcements of a replica.
+    Presence {
+        user_id: u64,
+        status: Presence,
+        seq: u64,
+    },
+    /// Presence of the users connected to the origin replica, sent periodically so
+    /// the presence of replicas which went away without a word expires.
+    Heartbeat { users: Vec<(u64, Presence, u64)> },
+}
+
+/// Fan-out within a single process, for tests and single replica deployments.
+#[derive(Debug)]
+pub struct MemoryFanOut {
+    tx: broadcast::Sender<FanOutMessage>,
+}
+
+/// Fan-out over Postgres NOTIFY, each replica listens to the same channel.
+pub struct PgFanOut {
+    pool: PgPool,
+    tx: broadcast::Sender<FanOutMessage>,
+}
+
+impl MemoryFanOut {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { tx }
+    }
+}
+
+impl Default for MemoryFanOut {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FanOut for MemoryFanOut {
+    fn publish(&self, msg: FanOutMessage) -> BoxFuture<'_, anyhow::Result<()>> {
+        // no receivers is fine, nobody is listening yet
+        let _ = self.tx.send(msg);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<FanOutMessage> {
+        self.tx.subscribe()
+    }
+}
+
+impl PgFanOut {
+    pub async fn try_new(db_url: &str, pool: PgPool) -> anyhow::Result<Self> {
+        let mut listener = PgNotifications::connect(db_url, &[FANOUT_CHANNEL]).await?;
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        let sender = tx.clone();
+        tokio::spawn(async move {
+            loop {
+                let notif = listener.recv().await;
+                match serde_json::from_str::<FanOutMessage>(notif.payload()) {
+                    Ok(msg) => {
+                        let _ = sender.send(msg);
+                    }
+                    Err(e) => {
+                        warn!("Skipping invalid fan-out message: {}", e);
+                        listener.metrics().invalid_payload();
+                    }
+                }
+            }
+        });
+        Ok(Self { pool, tx })
+    }
+}
+
+impl FanOut for PgFanOut {
+    fn publish(&self, msg: FanOutMessage) -> BoxFuture<'_, anyhow::Result<()>> {
+        Box::pin(async move {
+            let payload = serde_json::to_string(&msg)?;
+            // a dropped connection fails a single publish, retry once after a short pause
+            let mut backoff = Backoff::default();
+            for attempt in 0..2 {
+                match sqlx::query("SELECT pg_notify($1, $2)")
+                    .bind(FANOUT_CHANNEL)
+                    .bind(&payload)
+                    .execute(&self.pool)
+                    .await
+                {
+                    Ok(_) => break,
+                    Err(e) if attempt == 0 => {
+                        warn!("Failed to publish fan-out message, retrying: {}", e);
+                        tokio::time::sleep(backoff.next_delay()).await;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<FanOutMessage> {
+        self.tx.subscribe()
+    }
+}
+
+impl AppState {
+    pub(crate) async fn publish(&self, body: FanOutBody) -> anyhow::Result<()> {
+        let msg = FanOutMessage {
+            origin: self.replica_id,
+            body,
+        };
+        self.fanout.publish(msg).await
+    }
+
+    /// Send an ephemeral event to the users among `user_ids`, whichever replica they
+    /// are connected to.
+    pub(crate) async fn fan_out(&self, user_ids: Vec<u64>, event: AppEvent) {
+        if let Err(e) = self.publish(FanOutBody::Deliver { user_ids, event }).await {
+            warn!("Failed to publish event: {}", e);
+        }
+    }
+}
+
+/// Handle the messages of all replicas and announce the presence of this one.
+pub(crate) fn setup_fanout(state: AppState) {
+    info!("Starting fan-out for replica {}", state.replica_id);
+    let mut rx = state.fanout.subscribe();
+    let receiver = state.clone();
+    tokio::spawn(async move {
+        loop {
+            let msg = match rx.recv().await {
+                Ok(msg) => msg,
+                Err(RecvError::Lagged(n)) => {
+                    // heartbeats repair presence, typing indicators are short lived anyway
+                    warn!("Fan-out lagged behind, skipped {} messages", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let state = &receiver;
+            match msg.body {
+                FanOutBody::Deliver { user_ids, event } => {
+                    state.deliver(user_ids, Arc::new(LoggedEvent::ephemeral(event)));
+                }
+                FanOutBody::Presence {
+                    user_id,
+                    status,
+                    seq,
+                } => {
+                    state.apply_presence(msg.origin, user_id, status, seq).await;
+                }
+                FanOutBody::Heartbeat { users } => {
+                    for (user_id, status, seq) in users {
+                        state.apply_presence(msg.origin, user_id, status, seq).await;
+                    }
+                }
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut ticks = 0u32;
+        loop {
+            interval.tick().await;
+            state.heartbeat().await;
+            // wait until every live replica had a chance to announce its users
+            ticks = ticks.saturating_add(1);
+            if ticks > 3 {
+                if let Err(e) = state.reconcile_presence().await {
+                    warn!("Failed to reconcile presence: {}", e);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_fanout_should_reach_every_subscriber() -> anyhow::Result<()> {
+        let fanout = MemoryFanOut::new();
+        let mut rx1 = fanout.subscribe();
+        let mut rx2 = fanout.subscribe();
+        let origin = Uuid::now_v7();
+        fanout
+            .publish(FanOutMessage {
+                origin,
+                body: FanOutBody::Presence {
+                    user_id: 1,
+                    status: Presence::Online,
+                    seq: 1,
+                },
+            })
+            .await?;
+        for rx in [&mut rx1, &mut rx2] {
+            let msg = rx.recv().await?;
+            assert_eq!(msg.origin, origin);
+            assert!(matches!(
+                msg.body,
+                FanOutBody::Presence {
+                    user_id: 1,
+                    status: Presence::Online,
+                    seq: 1
+                }
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fanout_message_should_round_trip() -> anyhow::Result<()> {
+        let msg = FanOutMessage {
+            origin: Uuid::now_v7(),
+            body: FanOutBody::Heartbeat {
+                users: vec![(1, Presence::Online, 1), (2, Presence::Away, 2)],
+            },
+        };
+        let json = serde_json::to_string(&msg)?;
+        let msg: FanOutMessage = serde_json::from_str(&json)?;
+        assert!(matches!(msg.body, FanOutBody::Heartbeat { users } if users.len() == 2));
+        Ok(())
+    }
+}
@@ -1,39 +1,57 @@
 mod config;
 mod error;
+mod fanout;
 mod notify;
+mod presence;
+mod settings;
 mod sse;
+mod ws;
 use std::{ops::Deref, sync::Arc};
 
 use axum::{
+    extract::State,
     http::Method,
     middleware::from_fn_with_state,
     response::{Html, IntoResponse},
     routing::get,
-    Router,
+    Json, Router,
 };
-use chat_core::{verify_token, DecodingKey, TokenVerify, User};
+use chat_core::{verify_token, DecodingKey, ListenerMetrics, TokenVerify, User};
 use dashmap::DashMap;
+use sqlx::{postgres::PgPoolOptions, PgPool};
 use sse::sse_handler;
 use tokio::sync::broadcast;
+use ws::ws_handler;
 
 pub use config::AppConfig;
 pub use error::AppError;
-pub use notify::AppEvent;
+pub use fanout::{FanOut, FanOutBody, FanOutMessage, MemoryFanOut, PgFanOut};
+pub use notify::{AppEvent, ConnectionStats, LoggedEvent, MessageRead, Typing, UserConnections};
+pub use presence::{PresenceChanged, PresenceMap, PresenceView};
 use tower_http::cors::{Any, CorsLayer};
+use uuid::Uuid;
+pub use ws::ClientFrame;
 
 const INDEX_HTML: &str = include_str!("../index.html");
-pub type UserMap = Arc<DashMap<u64, broadcast::Sender<Arc<AppEvent>>>>;
+pub type UserMap = Arc<DashMap<u64, broadcast::Sender<Arc<LoggedEvent>>>>;
 
 #[derive(Clone)]
 pub struct AppState(Arc<AppStateInner>);
 pub struct AppStateInner {
     pub config: AppConfig,
     users: UserMap,
+    presence: PresenceMap,
+    /// presence across all replicas
+    view: PresenceView,
+    replica_id: Uuid,
+    fanout: Arc<dyn FanOut>,
     dk: DecodingKey,
+    pool: PgPool,
+    listener_metrics: ListenerMetrics,
 }
 
 pub async fn get_router(config: AppConfig) -> anyhow::Result<Router> {
-    let state = AppState::new(config);
+    let state = AppState::try_new(config).await?;
     let cors = CorsLayer::new()
         .allow_methods([
             Method::GET,
@@ -44,9 +62,13 @@ pub async fn get_router(config: AppConfig) -> anyhow::Result<Router> {
         ])
         .allow_headers(Any)
         .allow_origin(Any);
+    fanout::setup_fanout(state.clone());
     notify::setup_pg_listener(state.clone()).await?;
     let app = Router::new()
         .route("/events", get(sse_handler))
+        .route("/ws", get(ws_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/stats", get(stats_handler))
         .layer(from_fn_with_state(state.clone(), verify_token::<AppState>))
         .route("/", get(index_handler))
         .layer(cors)
@@ -58,6 +80,16 @@ async fn index_handler() -> impl IntoResponse {
     Html(INDEX_HTML)
 }
 
+/// Counters of the Postgres listener, e.g. how often it had to reconnect.
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.listener_metrics.stats())
+}
+
+/// Open connections per user on this replica.
+async fn stats_handler(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.connection_stats())
+}
+
 impl Deref for AppState {
     type Target = AppStateInner;
     fn deref(&self) -> &Self::Target {
@@ -68,14 +100,38 @@ impl Deref for AppState {
 impl TokenVerify for AppState {
     type Error = AppError;
     fn verify(&self, token: &str) -> Result<User, Self::Error> {
-        Ok(self.dk.verify(token)?)
+        self.dk.verify(token).map_err(AppError::JwtError)
     }
 }
 
 impl AppState {
-    pub fn new(config: AppConfig) -> Self {
+    /// State whose ephemeral events reach the other replicas over Postgres.
+    pub async fn try_new(config: AppConfig) -> anyhow::Result<Self> {
+        let pool = PgPoolOptions::new().connect_lazy(&config.server.db_url)?;
+        let fanout = PgFanOut::try_new(&config.server.db_url, pool.clone()).await?;
+        Ok(Self::with_pool(config, pool, Arc::new(fanout)))
+    }
+
+    pub fn new_with_fanout(config: AppConfig, fanout: Arc<dyn FanOut>) -> Self {
+        let pool = PgPoolOptions::new()
+            .connect_lazy(&config.server.db_url)
+            .expect("Failed to create db pool");
+        Self::with_pool(config, pool, fanout)
+    }
+
+    fn with_pool(config: AppConfig, pool: PgPool, fanout: Arc<dyn FanOut>) -> Self {
         let dk = DecodingKey::load(&config.auth.pk).expect("Failed to load pk");
         let users = Arc::new(DashMap::new());
-        Self(Arc::new(AppStateInner { config, dk, users }))
+        Self(Arc::new(AppStateInner {
+            config,
+            dk,
+            users,
+            presence: PresenceMap::default(),
+            view: PresenceView::default(),
+            replica_id: Uuid::now_v7(),
+            fanout,
+            pool,
+            listener_metrics: ListenerMetrics::default(),
+        }))
     }
 }
@@ -0,0 +1,436 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use chat_core::Presence;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{AppEvent, AppState, FanOutBody, LoggedEvent};
+
+/// How often each replica announces the presence of its users.
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// Presence not refreshed for this long belongs to a replica which went away.
+const PRESENCE_TTL: Duration = Duration::from_secs(90);
+
+/// Presence of the connections to this replica.
+///
+/// A user is online if any of their connections is, away if all of them are away,
+/// and offline once the last one is closed. Each change gets a sequence number, so
+/// other replicas can drop announcements which arrive out of order.
+#[derive(Debug, Default)]
+pub struct PresenceMap {
+    users: DashMap<u64, LocalPresence>,
+    next_conn_id: AtomicU64,
+    seq: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct LocalPresence {
+    conns: HashMap<u64, Presence>,
+    seq: u64,
+}
+
+/// Presence of users across all replicas, as announced over the fan-out.
+#[derive(Debug, Default)]
+pub struct PresenceView {
+    users: DashMap<u64, HashMap<Uuid, ReplicaPresence>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ReplicaPresence {
+    status: Presence,
+    seq: u64,
+    seen_at: Instant,
+}
+
+/// The presence of a user changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresenceChanged {
+    pub user_id: i64,
+    pub status: Presence,
+}
+
+/// Marks its connection as closed when dropped, e.g. with the event stream.
+pub(crate) struct Connection {
+    state: AppState,
+    user_id: u64,
+    conn_id: u64,
+}
+
+impl PresenceMap {
+    /// Register a new connection, online, and return its id.
+    pub fn connect(&self, user_id: u64) -> (u64, Option<Presence>) {
+        let conn_id = self.next_conn_id.fetch_add(1, Ordering::Relaxed);
+        let changed = self.update(user_id, |conns| {
+            conns.insert(conn_id, Presence::Online);
+        });
+        (conn_id, changed)
+    }
+
+    /// Set the presence of a connection, e.g. away when the app is idle.
+    pub fn set(&self, user_id: u64, conn_id: u64, presence: Presence) -> Option<Presence> {
+        self.update(user_id, |conns| {
+            if let Some(p) = conns.get_mut(&conn_id) {
+                *p = presence;
+            }
+        })
+    }
+
+    pub fn disconnect(&self, user_id: u64, conn_id: u64) -> Option<Presence> {
+        self.update(user_id, |conns| {
+            conns.remove(&conn_id);
+        })
+    }
+
+    /// Presence of a user on this replica, with the sequence number of its last change.
+    pub fn get(&self, user_id: u64) -> (Presence, u64) {
+        self.users
+            .get(&user_id)
+            .map(|p| (combine(p.conns.values().copied()), p.seq))
+            .unwrap_or((Presence::Offline, self.seq.load(Ordering::Relaxed)))
+    }
+
+    /// Number of open connections of a user.
+    pub fn connections(&self, user_id: u64) -> usize {
+        self.users.get(&user_id).map(|p| p.conns.len()).unwrap_or(0)
+    }
+
+    /// Presence of the users connected to this replica, with their sequence numbers.
+    pub fn snapshot(&self) -> Vec<(u64, Presence, u64)> {
+        self.users
+            .iter()
+            .map(|p| (*p.key(), combine(p.conns.values().copied()), p.seq))
+            .collect()
+    }
+
+    /// Apply `f` to the connections of a user, returns the new presence if it changed.
+    fn update(
+        &self,
+        user_id: u64,
+        f: impl FnOnce(&mut HashMap<u64, Presence>),
+    ) -> Option<Presence> {
+        let mut local = self.users.entry(user_id).or_default();
+        let before = combine(local.conns.values().copied());
+        f(&mut local.conns);
+        let after = combine(local.conns.values().copied());
+        if before != after {
+            local.seq = self.seq.fetch_add(1, Ordering::Relaxed) + 1;
+        }
+        let empty = local.conns.is_empty();
+        drop(local);
+        if empty {
+            self.users.remove_if(&user_id, |_, p| p.conns.is_empty());
+        }
+        (before != after).then_some(after)
+    }
+}
+
+impl PresenceView {
+    /// Apply the presence a replica announced, returns the new presence if it changed.
+    pub fn apply(
+        &self,
+        replica: Uuid,
+        user_id: u64,
+        status: Presence,
+        seq: u64,
+        now: Instant,
+    ) -> Option<Presence> {
+        let mut replicas = self.users.entry(user_id).or_default();
+        let before = combine(replicas.values().map(|p| p.status));
+        let entry = replicas.entry(replica).or_insert(ReplicaPresence {
+            status,
+            seq,
+            seen_at: now,
+        });
+        // an older announcement which arrived late
+        if entry.seq > seq {
+            return None;
+        }
+        *entry = ReplicaPresence {
+            status,
+            seq,
+            seen_at: now,
+        };
+        let after = combine(replicas.values().map(|p| p.status));
+        (before != after).then_some(after)
+    }
+
+    pub fn get(&self, user_id: u64) -> Presence {
+        self.users
+            .get(&user_id)
+            .map(|replicas| combine(replicas.values().map(|p| p.status)))
+            .unwrap_or_default()
+    }
+
+    /// Forget the presence replicas stopped refreshing, returns the users whose
+    /// presence changed.
+    pub fn expire(&self, now: Instant) -> Vec<(u64, Presence)> {
+        let mut changed = Vec::new();
+        self.users.retain(|user_id, replicas| {
+            let before = combine(replicas.values().map(|p| p.status));
+            replicas.retain(|_, p| now.duration_since(p.seen_at) < PRESENCE_TTL);
+            let after = combine(replicas.values().map(|p| p.status));
+            if before != after {
+                changed.push((*user_id, after));
+            }
+            !replicas.is_empty()
+        });
+        changed
+    }
+
+    /// Users online or away on any replica.
+    pub fn present_users(&self) -> Vec<i64> {
+        self.users
+            .iter()
+            .filter(|r| combine(r.values().map(|p| p.status)) != Presence::Offline)
+            .map(|r| *r.key() as i64)
+            .collect()
+    }
+}
+
+/// Online if any is online, away if any is away, offline otherwise.
+fn combine(statuses: impl IntoIterator<Item = Presence>) -> Presence {
+    let mut ret = Presence::Offline;
+    for status in statuses {
+        match status {
+            Presence::Online => return Presence::Online,
+            Presence::Away => ret = Presence::Away,
+            Presence::Offline => {}
+        }
+    }
+    ret
+}
+
+impl Connection {
+    pub(crate) fn open(state: &AppState, user_id: u64) -> Self {
+        let (conn_id, changed) = state.presence.connect(user_id);
+        if changed.is_some() {
+            tokio::spawn(state.clone().announce_presence(user_id));
+        }
+        Self {
+            state: state.clone(),
+            user_id,
+            conn_id,
+        }
+    }
+
+    pub(crate) fn set(&self, presence: Presence) {
+        let changed = self
+            .state
+            .presence
+            .set(self.user_id, self.conn_id, presence);
+        if changed.is_some() {
+            tokio::spawn(self.state.clone().announce_presence(self.user_id));
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let changed = self.state.presence.disconnect(self.user_id, self.conn_id);
+        self.state.evict(self.user_id);
+        if changed.is_some() {
+            tokio::spawn(self.state.clone().announce_presence(self.user_id));
+        }
+    }
+}
+
+impl AppState {
+    /// Tell every replica the presence of a user on this one.
+    ///
+    /// The current presence is announced rather than the change which triggered it, and
+    /// replicas drop announcements older than the last one they applied.
+    async fn announce_presence(self, user_id: u64) {
+        let (status, seq) = self.presence.get(user_id);
+        let body = FanOutBody::Presence {
+            user_id,
+            status,
+            seq,
+        };
+        if let Err(e) = self.publish(body).await {
+            warn!("Failed to announce presence of user {}: {}", user_id, e);
+        }
+    }
+
+    /// Announce the presence of the users connected to this replica and forget the
+    /// presence of replicas which went away.
+    pub(crate) async fn heartbeat(&self) {
+        // a chunk per message, to stay well below the pg_notify payload limit
+        const CHUNK_SIZE: usize = 100;
+        for users in self.presence.snapshot().chunks(CHUNK_SIZE) {
+            let body = FanOutBody::Heartbeat {
+                users: users.to_vec(),
+            };
+            if let Err(e) = self.publish(body).await {
+                warn!("Failed to publish presence heartbeat: {}", e);
+            }
+        }
+        // every replica notices an expiry and tells its own users, storing is idempotent
+        for (user_id, status) in self.view.expire(Instant::now()) {
+            self.presence_changed(user_id, status, true).await;
+        }
+    }
+
+    /// Apply a presence announcement of a replica.
+    pub(crate) async fn apply_presence(
+        &self,
+        origin: Uuid,
+        user_id: u64,
+        status: Presence,
+        seq: u64,
+    ) {
+        let changed = self
+            .view
+            .apply(origin, user_id, status, seq, Instant::now());
+        if let Some(status) = changed {
+            // the replica which announced the change stores it
+            self.presence_changed(user_id, status, origin == self.replica_id)
+                .await;
+        }
+    }
+
+    /// Store the presence of a user if `store`, and tell the users of this replica who
+    /// share a chat with them.
+    async fn presence_changed(&self, user_id: u64, status: Presence, store: bool) {
+        info!("User {} is {:?}", user_id, status);
+        if store {
+            if let Err(e) = self.store_presence(user_id, status).await {
+                warn!("Failed to store presence of user {}: {}", user_id, e);
+            }
+        }
+        if self.users.is_empty() {
+            return;
+        }
+        let contacts: Vec<(i64,)> = match sqlx::query_as(
+            r#"
+            SELECT DISTINCT unnest(members) FROM chats WHERE $1 = ANY(members)
+            "#,
+        )
+        .bind(user_id as i64)
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(contacts) => contacts,
+            Err(e) => {
+                warn!("Failed to load contacts of user {}: {}", user_id, e);
+                return;
+            }
+        };
+        let event = AppEvent::PresenceChanged(PresenceChanged {
+            user_id: user_id as _,
+            status,
+        });
+        self.deliver(
+            contacts
+                .into_iter()
+                .map(|(id,)| id as u64)
+                .filter(|id| *id != user_id),
+            Arc::new(LoggedEvent::ephemeral(event)),
+        );
+    }
+
+    async fn store_presence(&self, user_id: u64, status: Presence) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO user_presence (user_id, status)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE SET status = $2, updated_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(user_id as i64)
+        .bind(status)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Set users no replica knows about offline, e.g. after every replica restarted.
+    ///
+    /// Only rows untouched for longer than the presence ttl are reset, so a change
+    /// still on its way through the fan-out is left alone.
+    pub(crate) async fn reconcile_presence(&self) -> anyhow::Result<()> {
+        let ret = sqlx::query(
+            r#"
+            UPDATE user_presence
+            SET status = 'offline', updated_at = CURRENT_TIMESTAMP
+            WHERE status <> 'offline'
+            AND updated_at < NOW() - make_interval(secs => $1)
+            AND NOT (user_id = ANY($2))
+            "#,
+        )
+        .bind(PRESENCE_TTL.as_secs_f64())
+        .bind(self.view.present_users())
+        .execute(&self.pool)
+        .await?;
+        if ret.rows_affected() > 0 {
+            info!("Reset stale presence of {} users", ret.rows_affected());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presence_should_follow_connections() {
+        let map = PresenceMap::default();
+        let (a, changed) = map.connect(1);
+        assert_eq!(changed, Some(Presence::Online));
+        let (b, changed) = map.connect(1);
+        assert_eq!(changed, None);
+        assert_eq!(map.connections(1), 2);
+
+        // away only once every connection is away
+        assert_eq!(map.set(1, a, Presence::Away), None);
+        assert_eq!(map.set(1, b, Presence::Away), Some(Presence::Away));
+        assert_eq!(map.get(1).0, Presence::Away);
+
+        assert_eq!(map.disconnect(1, a), None);
+        assert_eq!(map.disconnect(1, b), Some(Presence::Offline));
+        assert_eq!(map.get(1).0, Presence::Offline);
+        assert_eq!(map.connections(1), 0);
+    }
+
+    #[test]
+    fn presence_view_should_combine_replicas() {
+        let view = PresenceView::default();
+        let (r1, r2) = (Uuid::now_v7(), Uuid::now_v7());
+        let now = Instant::now();
+
+        assert_eq!(
+            view.apply(r1, 1, Presence::Away, 1, now),
+            Some(Presence::Away)
+        );
+        assert_eq!(
+            view.apply(r2, 1, Presence::Online, 1, now),
+            Some(Presence::Online)
+        );
+        // a late, out of order announcement is dropped
+        assert_eq!(
+            view.apply(r2, 1, Presence::Offline, 3, now),
+            Some(Presence::Away)
+        );
+        assert_eq!(view.apply(r2, 1, Presence::Online, 2, now), None);
+        assert_eq!(view.get(1), Presence::Away);
+        assert_eq!(view.present_users(), vec![1]);
+
+        // nobody refreshes anymore
+        assert_eq!(
+            view.expire(now + PRESENCE_TTL),
+            vec![(1, Presence::Offline)]
+        );
+        assert_eq!(view.get(1), Presence::Offline);
+        assert!(view.present_users().is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::{HashMap, HashSet};
+
+use chat_core::{mention_handle, mentions, Message, NotificationLevel};
+use chrono::NaiveTime;
+use sqlx::FromRow;
+use tracing::warn;
+
+use crate::AppState;
+
+/// Notification settings of a member for a chat, chat settings override the defaults.
+#[derive(Debug, FromRow)]
+struct RecipientSettings {
+    id: i64,
+    chat_id: i64,
+    email: String,
+    level: NotificationLevel,
+    dnd_start: Option<NaiveTime>,
+    dnd_end: Option<NaiveTime>,
+}
+
+impl AppState {
+    /// Members alerted of a new message by their notification settings. Every member
+    /// gets the message, the settings only decide whether it alerts them.
+    ///
+    /// The sender is never alerted, and only members connected to this replica are looked
+    /// up, the others don't get it live. If the settings can't be loaded every member is.
+    pub(crate) async fn alerted_members(
+        &self,
+        message: &Message,
+        members: &HashSet<u64>,
+    ) -> HashSet<u64> {
+        let connected: Vec<i64> = members
+            .iter()
+            .filter(|id| **id != message.sender_id as u64 && self.users.contains_key(id))
+            .map(|id| *id as i64)
+            .collect();
+        if connected.is_empty() {
+            return HashSet::new();
+        }
+        match self
+            .recipient_settings(&connected, &[message.chat_id])
+            .await
+        {
+            Ok(settings) => settings
+                .iter()
+                .filter(|v| v.notifies(message))
+                .map(|v| v.id as u64)
+                .collect(),
+            Err(e) => {
+                warn!("Failed to load notification settings: {}", e);
+                connected.into_iter().map(|id| id as u64).collect()
+            }
+        }
+    }
+
+    /// Ids of the replayed messages which alert `user_id`, the same ones as when they
+    /// were delivered live.
+    pub(crate) async fn alerting_messages(
+        &self,
+        user_id: u64,
+        messages: &[&Message],
+    ) -> HashSet<i64> {
+        let messages: Vec<_> = messages
+            .iter()
+            .filter(|m| m.sender_id as u64 != user_id)
+            .collect();
+        let mut chat_ids: Vec<i64> = messages.iter().map(|m| m.chat_id).collect();
+        chat_ids.sort();
+        chat_ids.dedup();
+        if chat_ids.is_empty() {
+            return HashSet::new();
+        }
+        let settings: HashMap<i64, RecipientSettings> =
+            match self.recipient_settings(&[user_id as i64], &chat_ids).await {
+                Ok(settings) => settings.into_iter().map(|v| (v.chat_id, v)).collect(),
+                Err(e) => {
+                    warn!("Failed to load notification settings: {}", e);
+                    return messages.iter().map(|m| m.id).collect();
+                }
+            };
+        messages
+            .iter()
+            .filter(|m| settings.get(&m.chat_id).is_some_and(|v| v.notifies(m)))
+            .map(|m| m.id)
+            .collect()
+    }
+
+    /// Settings of each of `user_ids` for each of `chat_ids`.
+    async fn recipient_settings(
+        &self,
+        user_ids: &[i64],
+        chat_ids: &[i64],
+    ) -> Result<Vec<RecipientSettings>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT u.id, c.chat_id, u.email,
+                COALESCE(s.level, d.level, 'all') AS level,
+                COALESCE(s.dnd_start, d.dnd_start) AS dnd_start,
+                COALESCE(s.dnd_end, d.dnd_end) AS dnd_end
+            FROM users u
+            CROSS JOIN UNNEST($2::bigint[]) AS c(chat_id)
+            LEFT JOIN notification_settings d ON d.user_id = u.id AND d.chat_id = 0
+            LEFT JOIN notification_settings s ON s.user_id = u.id AND s.chat_id = c.chat_id
+            WHERE u.id = ANY($1)
+            "#,
+        )
+        .bind(user_ids)
+        .bind(chat_ids)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+impl RecipientSettings {
+    /// Whether the message alerts, do not disturb applies to the time it was sent.
+    fn notifies(&self, message: &Message) -> bool {
+        if self.in_dnd(message.created_at.time()) {
+            return false;
+        }
+        match self.level {
+            NotificationLevel::All => true,
+            NotificationLevel::Mentions => mentions(&message.content, &mention_handle(&self.email)),
+            NotificationLevel::Muted => false,
+        }
+    }
+
+    /// Whether `now` is within the do not disturb schedule, which may span midnight.
+    fn in_dnd(&self, now: NaiveTime) -> bool {
+        match (self.dnd_start, self.dnd_end) {
+            (Some(start), Some(end)) if start <= end => start <= now && now < end,
+            (Some(start), Some(end)) => now >= start || now < end,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifies_should_follow_level_and_dnd() {
+        let mut settings = RecipientSettings {
+            id: 2,
+            chat_id: 1,
+            email: "alice@acme.org".to_string(),
+            level: NotificationLevel::Mentions,
+            dnd_start: None,
+            dnd_end: None,
+        };
+        let noon = 12;
+        assert!(!settings.notifies(&message("hello all", noon)));
+        assert!(settings.notifies(&message("@Alice hello", noon)));
+
+        settings.level = NotificationLevel::Muted;
+        assert!(!settings.notifies(&message("@alice hello", noon)));
+
+        // do not disturb over midnight
+        settings.level = NotificationLevel::All;
+        settings.dnd_start = Some(time(22));
+        settings.dnd_end = Some(time(7));
+        assert!(settings.notifies(&message("hello", noon)));
+        assert!(!settings.notifies(&message("hello", 23)));
+        assert!(!settings.notifies(&message("hello", 6)));
+        assert!(settings.notifies(&message("hello", 7)));
+
+        settings.dnd_start = Some(time(9));
+        settings.dnd_end = Some(time(17));
+        assert!(!settings.notifies(&message("hello", noon)));
+        assert!(settings.notifies(&message("hello", 18)));
+    }
+
+    fn time(hour: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    /// A message sent at `hour` utc.
+    fn message(content: &str, hour: u32) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "chat_id": 1,
+            "sender_id": 1,
+            "content": content,
+            "modified_content": null,
+            "files": [],
+            "created_at": format!("2024-12-09T{:02}:00:00Z", hour),
+        }))
+        .unwrap()
+    }
+}
@@ -2,51 +2,47 @@ use std::{convert::Infallible, time::Duration};
 
 use axum::{
     extract::State,
+    http::HeaderMap,
     response::{sse::Event, Sse},
     Extension,
 };
 use chat_core::User;
 use futures::Stream;
-use tokio::sync::broadcast;
-use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use tokio_stream::StreamExt;
 use tracing::info;
 
-use crate::{AppEvent, AppState};
+use crate::{AppError, AppState};
 
-const CHANNEL_CAPACITY: usize = 256;
+const LAST_EVENT_ID: &str = "last-event-id";
 
 pub(crate) async fn sse_handler(
     Extension(user): Extension<User>,
     State(state): State<AppState>,
-) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
     let user_id = user.id as u64;
     /* let user_id = 1; */
-    let users = &state.users;
+    let last_id = headers
+        .get(LAST_EVENT_ID)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<i64>().ok());
+    // sse is one way, the connection lives as long as the stream
+    let (_, events) = state.subscribe(user_id, last_id).await?;
 
-    let rx = if let Some(rx) = users.get(&user_id) {
-        rx.subscribe()
-    } else {
-        let (tx, rx) = broadcast::channel(CHANNEL_CAPACITY);
-        users.insert(user_id, tx);
-        rx
-    };
-    info!("User {} subscribed", user_id);
-
-    let stream = BroadcastStream::new(rx).filter_map(|v| v.ok()).map(|v| {
-        let name = match v.as_ref() {
-            AppEvent::NewChat(_) => "NewChat",
-            AppEvent::AddToChat(_) => "AddToChat",
-            AppEvent::RemoveFromChat(_) => "RemoveFromChat",
-            AppEvent::NewMessage(_) => "NewMessage",
-        };
-        let v = serde_json::to_string(&v).expect("Failed to serialize event");
-        info!("Sending event {}: {:?}", name, v);
-        Ok(Event::default().data(v).event(name))
+    let stream = events.map(|v| {
+        let name = v.event.name();
+        let data = serde_json::to_string(&*v).expect("Failed to serialize event");
+        info!("Sending event {}: {:?}", name, data);
+        let event = Event::default().data(data).event(name);
+        Ok(match v.id {
+            Some(id) => event.id(id.to_string()),
+            None => event,
+        })
     });
 
-    Sse::new(stream).keep_alive(
+    Ok(Sse::new(stream).keep_alive(
         axum::response::sse::KeepAlive::new()
             .interval(Duration::from_secs(1))
             .text("keep-alive-text"),
-    )
+    ))
 }
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        Query, State, WebSocketUpgrade,
+    },
+    response::IntoResponse,
+    Extension,
+};
+use chat_core::{Presence, User};
+use futures::{SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{presence::Connection, AppError, AppEvent, AppState, LoggedEvent, MessageRead, Typing};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WsParams {
+    /// id of the last event received, like `Last-Event-ID` for SSE
+    last_event_id: Option<i64>,
+}
+
+/// Frames sent by clients over the websocket.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all_fields = "camelCase")]
+pub enum ClientFrame {
+    Typing {
+        chat_id: i64,
+    },
+    Read {
+        chat_id: i64,
+        message_id: i64,
+    },
+    /// Online or away, e.g. when the app goes idle
+    Presence {
+        status: Presence,
+    },
+}
+
+/// Events as json text frames, the same stream as `/events`, plus client frames for
+/// typing indicators and read acks.
+pub(crate) async fn ws_handler(
+    Extension(user): Extension<User>,
+    State(state): State<AppState>,
+    Query(params): Query<WsParams>,
+    ws: WebSocketUpgrade,
+) -> Result<impl IntoResponse, AppError> {
+    let (conn, events) = state.subscribe(user.id as _, params.last_event_id).await?;
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, state, user, conn, events)))
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    state: AppState,
+    user: User,
+    conn: Arc<Connection>,
+    events: impl Stream<Item = Arc<LoggedEvent>>,
+) {
+    let (mut sender, mut receiver) = socket.split();
+
+    let send = async {
+        let mut events = std::pin::pin!(events);
+        while let Some(v) = events.next().await {
+            let data = serde_json::to_string(&*v).expect("Failed to serialize event");
+            if sender.send(Message::Text(data)).await.is_err() {
+                break;
+            }
+        }
+    };
+    let recv = async {
+        while let Some(Ok(msg)) = receiver.next().await {
+            match msg {
+                Message::Text(text) => {
+                    if let Err(e) = handle_frame(&state, &user, &conn, &text).await {
+                        warn!("Invalid frame from user {}: {}", user.id, e);
+                    }
+                }
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+    };
+
+    // either side ending closes the connection
+    tokio::select! {
+        _ = send => {},
+        _ = recv => {},
+    }
+    info!("User {} disconnected", user.id);
+}
+
+async fn handle_frame(
+    state: &AppState,
+    user: &User,
+    conn: &Connection,
+    text: &str,
+) -> anyhow::Result<()> {
+    let frame: ClientFrame = serde_json::from_str(text)?;
+    let (chat_id, event) = match frame {
+        ClientFrame::Presence { status } => {
+            if status == Presence::Offline {
+                anyhow::bail!("presence can only be online or away");
+            }
+            conn.set(status);
+            return Ok(());
+        }
+        ClientFrame::Typing { chat_id } => (
+            chat_id,
+            AppEvent::Typing(Typing {
+                chat_id,
+                user_id: user.id,
+            }),
+        ),
+        ClientFrame::Read {
+            chat_id,
+            message_id,
+        } => {
+            state.mark_read(chat_id, user.id, message_id).await?;
+            (
+                chat_id,
+                AppEvent::MessageRead(MessageRead {
+                    chat_id,
+                    user_id: user.id,
+                    message_id,
+                }),
+            )
+        }
+    };
+
+    let members = state.chat_members(chat_id, user.id).await?;
+    let others = members
+        .into_iter()
+        .filter(|id| *id != user.id)
+        .map(|id| id as u64)
+        .collect();
+    // members may be connected to any replica
+    state.fan_out(others, event).await;
+    Ok(())
+}
+
+impl AppState {
+    /// Members of a chat, `user_id` must be one of them.
+    async fn chat_members(&self, chat_id: i64, user_id: i64) -> anyhow::Result<Vec<i64>> {
+        let members: Option<(Vec<i64>,)> =
+            sqlx::query_as("SELECT members FROM chats WHERE id = $1 AND $2 = ANY(members)")
+                .bind(chat_id)
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        members
+            .map(|(members,)| members)
+            .ok_or_else(|| anyhow::anyhow!("user {} is not a member of chat {}", user_id, chat_id))
+    }
+
+    /// Record that a member read a chat up to a message, acks never move backwards.
+    async fn mark_read(&self, chat_id: i64, user_id: i64, message_id: i64) -> anyhow::Result<()> {
+        self.chat_members(chat_id, user_id).await?;
+        let ret = sqlx::query(
+            r#"
+            INSERT INTO chat_reads (chat_id, user_id, message_id)
+            SELECT chat_id, $2, id FROM messages WHERE id = $3 AND chat_id = $1
+            ON CONFLICT (chat_id, user_id) DO UPDATE
+            SET message_id = GREATEST(chat_reads.message_id, EXCLUDED.message_id),
+                read_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(chat_id)
+        .bind(user_id)
+        .bind(message_id)
+        .execute(&self.pool)
+        .await?;
+        if ret.rows_affected() == 0 {
+            anyhow::bail!("message {} does not exist in chat {}", message_id, chat_id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_frame_should_parse() -> anyhow::Result<()> {
+        let frame: ClientFrame = serde_json::from_str(r#"{"event":"Typing","chatId":1}"#)?;
+        assert!(matches!(frame, ClientFrame::Typing { chat_id: 1 }));
+        let frame: ClientFrame =
+            serde_json::from_str(r#"{"event":"Read","chatId":1,"messageId":10}"#)?;
+        assert!(matches!(
+            frame,
+            ClientFrame::Read {
+                chat_id: 1,
+                message_id: 10
+            }
+        ));
+        let frame: ClientFrame = serde_json::from_str(r#"{"event":"Presence","status":"away"}"#)?;
+        assert!(matches!(
+            frame,
+            ClientFrame::Presence {
+                status: Presence::Away
+            }
+        ));
+        assert!(serde_json::from_str::<ClientFrame>(r#"{"event":"Delete"}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ephemeral_event_should_have_no_id() -> anyhow::Result<()> {
+        let event = LoggedEvent::ephemeral(AppEvent::Typing(Typing {
+            chat_id: 1,
+            user_id: 2,
+        }));
+        assert_eq!(
+            serde_json::to_string(&event)?,
+            r#"{"event":"Typing","chatId":1,"userId":2}"#
+        );
+        Ok(())
+    }
+}
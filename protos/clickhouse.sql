@@ -9,6 +9,7 @@ CREATE TABLE analytics_events(
     system_locale String,
     system_timezone String,
     user_id Nullable(String),
+    workspace_id Nullable(String),
     ip Nullable(String),
     user_agent Nullable(String),
     geo_country Nullable(String),
@@ -50,60 +51,84 @@ ORDER BY
         server_ts
     );
 
--- Create aggregated sessions table
--- CREATE TABLE analytics.sessions(
---     date date,
---     client_id String,
---     session_id String,
---     app_version String,
---     system_os String,
---     system_arch String,
---     system_locale String,
---     system_timezone String,
---     user_id Nullable(String),
---     ip Nullable(String),
---     user_agent Nullable(String),
---     geo_country Nullable(String),
---     geo_region Nullable(String),
---     geo_city Nullable(String),
---     session_start SimpleAggregateFunction(min, DateTime64(3)),
---     session_end SimpleAggregateFunction(max, DateTime64(3)),
---     session_length SimpleAggregateFunction(sum, UInt64),
---     total_events UInt32
--- ) ENGINE = SummingMergeTree()
--- ORDER BY
---     (
---         date,
---         client_id,
---         session_id
---     );
--- -- Create materialized view to aggregate sessions
--- CREATE MATERIALIZED VIEW analytics.sessions_mv TO analytics.sessions AS
--- SELECT
---     toDate(server_ts) AS date,
---     client_id,
---     session_id,
---     any(app_version) AS app_version,
---     any(system_os) AS system_os,
---     any(system_arch) AS system_arch,
---     any(system_locale) AS system_locale,
---     any(system_timezone) AS system_timezone,
---     any(user_id) AS user_id,
---     any(ip) AS ip,
---     any(user_agent) AS user_agent,
---     any(geo_country) AS geo_country,
---     any(geo_region) AS geo_region,
---     any(geo_city) AS geo_city,
---     min(server_ts) AS session_start,
---     max(server_ts) AS session_end,
---     sum(duration) / 1000 AS session_length,
---     count(1) AS total_events
--- FROM
---     analytics.analytics_events
--- GROUP BY
---     date,
---     client_id,
---     session_id;
+-- upgrade deployments which inserted events into the former "analytics" table, once:
+-- RENAME TABLE analytics TO analytics_events;
+-- upgrade tables created before events carried the workspace of the user
+ALTER TABLE analytics_events ADD COLUMN IF NOT EXISTS workspace_id Nullable(String) AFTER user_id;
+
+-- Sessions aggregated from the events. Their first and last events are taken by the
+-- client clock, which orders the events of a session even when they are sent late.
+CREATE TABLE sessions(
+    date SimpleAggregateFunction(min, Date),
+    client_id String,
+    session_id String,
+    app_version SimpleAggregateFunction(any, String),
+    system_os SimpleAggregateFunction(any, String),
+    system_arch SimpleAggregateFunction(any, String),
+    system_locale SimpleAggregateFunction(any, String),
+    system_timezone SimpleAggregateFunction(any, String),
+    user_id SimpleAggregateFunction(anyLast, Nullable(String)),
+    workspace_id SimpleAggregateFunction(anyLast, Nullable(String)),
+    ip SimpleAggregateFunction(any, Nullable(String)),
+    user_agent SimpleAggregateFunction(any, Nullable(String)),
+    geo_country SimpleAggregateFunction(any, Nullable(String)),
+    geo_region SimpleAggregateFunction(any, Nullable(String)),
+    geo_city SimpleAggregateFunction(any, Nullable(String)),
+    session_start SimpleAggregateFunction(min, DateTime64(3)),
+    session_end SimpleAggregateFunction(max, DateTime64(3)),
+    total_events SimpleAggregateFunction(sum, UInt64)
+) ENGINE = AggregatingMergeTree()
+ORDER BY
+    (
+        client_id,
+        session_id
+    );
+
+CREATE MATERIALIZED VIEW sessions_mv TO sessions AS
+SELECT
+    min(toDate(client_ts)) AS date,
+    client_id,
+    session_id,
+    any(app_version) AS app_version,
+    any(system_os) AS system_os,
+    any(system_arch) AS system_arch,
+    any(system_locale) AS system_locale,
+    any(system_timezone) AS system_timezone,
+    anyLast(user_id) AS user_id,
+    anyLast(workspace_id) AS workspace_id,
+    any(ip) AS ip,
+    any(user_agent) AS user_agent,
+    any(geo_country) AS geo_country,
+    any(geo_region) AS geo_region,
+    any(geo_city) AS geo_city,
+    min(client_ts) AS session_start,
+    max(client_ts) AS session_end,
+    count() AS total_events
+FROM
+    analytics_events
+WHERE
+    session_id != ''
+GROUP BY
+    client_id,
+    session_id;
+
+-- Sessions with the rows of each insert merged, the length is in milliseconds.
+CREATE VIEW session_lengths AS
+SELECT
+    min(date) AS date,
+    client_id,
+    session_id,
+    anyLast(workspace_id) AS workspace_id,
+    min(session_start) AS session_start,
+    max(session_end) AS session_end,
+    dateDiff('millisecond', min(session_start), max(session_end)) AS session_length,
+    sum(total_events) AS total_events
+FROM
+    sessions
+GROUP BY
+    client_id,
+    session_id;
+
 -- -- populate sessions table
 -- -- INSERT INTO analytics.sessions...;
 -- -- query sessions table
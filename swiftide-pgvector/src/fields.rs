@@ -0,0 +1,42 @@
+use swiftide_core::indexing::EmbeddedField;
+
+/// The vector column an embedded field is stored in.
+///
+/// `Combined` keeps the original `embedding` column, other fields get their own
+/// `embedding_*` column.
+pub(crate) fn column_name(field: &EmbeddedField) -> String {
+    match field {
+        EmbeddedField::Combined => "embedding".to_string(),
+        EmbeddedField::Chunk => "embedding_chunk".to_string(),
+        EmbeddedField::Metadata(name) => format!("embedding_meta_{}", sanitize(name)),
+    }
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_name_should_work() {
+        assert_eq!(column_name(&EmbeddedField::Combined), "embedding");
+        assert_eq!(column_name(&EmbeddedField::Chunk), "embedding_chunk");
+        assert_eq!(
+            column_name(&EmbeddedField::Metadata(
+                "Questions and Answers".to_string()
+            )),
+            "embedding_meta_questions_and_answers"
+        );
+    }
+}
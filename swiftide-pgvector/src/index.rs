@@ -0,0 +1,150 @@
+/// The distance metric used to index and compare embeddings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DistanceMetric {
+    #[default]
+    Cosine,
+    L2,
+    InnerProduct,
+}
+
+/// The type of the approximate nearest neighbor index on the embedding column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexType {
+    /// HNSW index, `m` is the max connections per layer and `ef_construction`
+    /// the size of the candidate list when building the graph.
+    Hnsw { m: u32, ef_construction: u32 },
+    /// IVFFlat index, `lists` is the number of inverted lists.
+    IvfFlat { lists: u32 },
+}
+
+impl Default for IndexType {
+    fn default() -> Self {
+        Self::Hnsw {
+            m: 16,
+            ef_construction: 64,
+        }
+    }
+}
+
+impl DistanceMetric {
+    const ALL: [Self; 3] = [Self::Cosine, Self::L2, Self::InnerProduct];
+
+    /// The pgvector operator class used when creating the index.
+    pub fn ops(&self) -> &'static str {
+        match self {
+            Self::Cosine => "vector_cosine_ops",
+            Self::L2 => "vector_l2_ops",
+            Self::InnerProduct => "vector_ip_ops",
+        }
+    }
+
+    /// The pgvector distance operator matching the operator class.
+    pub fn operator(&self) -> &'static str {
+        match self {
+            Self::Cosine => "<=>",
+            Self::L2 => "<->",
+            Self::InnerProduct => "<#>",
+        }
+    }
+
+    fn short_name(&self) -> &'static str {
+        match self {
+            Self::Cosine => "cosine",
+            Self::L2 => "l2",
+            Self::InnerProduct => "ip",
+        }
+    }
+}
+
+impl IndexType {
+    const KINDS: [&'static str; 2] = ["hnsw", "ivfflat"];
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Hnsw { .. } => "hnsw",
+            Self::IvfFlat { .. } => "ivfflat",
+        }
+    }
+
+    /// Name of the index on `column`. It differs per index type and metric, so that
+    /// changing either creates a new index instead of keeping the existing one.
+    pub(crate) fn index_name(&self, table: &str, column: &str, metric: DistanceMetric) -> String {
+        index_name(table, column, self.kind(), metric)
+    }
+
+    /// Names of the indexes `column` may have from other index types and metrics,
+    /// including the name used before they were part of it.
+    pub(crate) fn other_index_names(
+        &self,
+        table: &str,
+        column: &str,
+        metric: DistanceMetric,
+    ) -> Vec<String> {
+        let current = self.index_name(table, column, metric);
+        let mut names = vec![format!("{}_{}_idx", table, column)];
+        for kind in Self::KINDS {
+            for metric in DistanceMetric::ALL {
+                names.push(index_name(table, column, kind, metric));
+            }
+        }
+        names.retain(|name| *name != current);
+        names
+    }
+
+    /// The `USING ... WITH (...)` clause of the `CREATE INDEX` statement.
+    pub(crate) fn using_clause(&self, column: &str, metric: DistanceMetric) -> String {
+        match self {
+            Self::Hnsw { m, ef_construction } => format!(
+                "USING hnsw ({} {}) WITH (m = {}, ef_construction = {})",
+                column,
+                metric.ops(),
+                m,
+                ef_construction
+            ),
+            Self::IvfFlat { lists } => format!(
+                "USING ivfflat ({} {}) WITH (lists = {})",
+                column,
+                metric.ops(),
+                lists
+            ),
+        }
+    }
+}
+
+fn index_name(table: &str, column: &str, kind: &str, metric: DistanceMetric) -> String {
+    format!("{}_{}_{}_{}_idx", table, column, kind, metric.short_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn using_clause_should_match_metric_and_index() {
+        let clause = IndexType::default().using_clause("embedding", DistanceMetric::Cosine);
+        assert_eq!(
+            clause,
+            "USING hnsw (embedding vector_cosine_ops) WITH (m = 16, ef_construction = 64)"
+        );
+
+        let clause = IndexType::IvfFlat { lists: 100 }
+            .using_clause("embedding_chunk", DistanceMetric::InnerProduct);
+        assert_eq!(
+            clause,
+            "USING ivfflat (embedding_chunk vector_ip_ops) WITH (lists = 100)"
+        );
+    }
+
+    #[test]
+    fn index_name_should_change_with_metric_and_index() {
+        let index = IndexType::default();
+        let name = index.index_name("chunks", "embedding", DistanceMetric::Cosine);
+        assert_eq!(name, "chunks_embedding_hnsw_cosine_idx");
+
+        let others = index.other_index_names("chunks", "embedding", DistanceMetric::Cosine);
+        assert_eq!(others.len(), 6);
+        assert!(!others.contains(&name));
+        assert!(others.contains(&"chunks_embedding_idx".to_string()));
+        assert!(others.contains(&"chunks_embedding_ivfflat_cosine_idx".to_string()));
+    }
+}
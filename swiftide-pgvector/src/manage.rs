@@ -0,0 +1,105 @@
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::prelude::FromRow;
+use swiftide_core::indexing::Node;
+use tracing::info;
+
+use crate::PgVector;
+
+/// A path stored in the table, with the number of chunks indexed from it.
+#[derive(Debug, Clone, FromRow, PartialEq, Eq)]
+pub struct IndexedPath {
+    pub path: String,
+    pub chunk_count: i64,
+    pub last_indexed_at: Option<DateTime<Utc>>,
+}
+
+impl PgVector {
+    /// Delete every chunk indexed from `path`, returns the number of deleted chunks.
+    #[tracing::instrument(skip(self, path), fields(path = %path.as_ref().display()))]
+    pub async fn delete_path(&self, path: impl AsRef<Path>) -> Result<u64> {
+        let path = path.as_ref().to_string_lossy();
+        let sql = format!("DELETE FROM {} WHERE path = $1", self.table_name);
+        let ret = sqlx::query(&sql)
+            .bind(path.as_ref())
+            .execute(self.get_pool())
+            .await?;
+        info!("deleted {} chunks", ret.rows_affected());
+        Ok(ret.rows_affected())
+    }
+
+    /// Atomically replace all chunks of `path` with `nodes`.
+    ///
+    /// Chunks that are no longer produced for the path are removed, so a changed
+    /// file doesn't leave stale chunks behind.
+    #[tracing::instrument(skip(self, path, nodes), fields(path = %path.as_ref().display()))]
+    pub async fn replace_path(&self, path: impl AsRef<Path>, nodes: &[Node]) -> Result<()> {
+        let path = path.as_ref().to_string_lossy();
+        if let Some(node) = nodes.iter().find(|n| n.path.to_string_lossy() != path) {
+            anyhow::bail!(
+                "Node path {} doesn't match replaced path {}",
+                node.path.display(),
+                path
+            );
+        }
+
+        let mut tx = self.get_pool().begin().await?;
+        let sql = format!("DELETE FROM {} WHERE path = $1", self.table_name);
+        sqlx::query(&sql)
+            .bind(path.as_ref())
+            .execute(&mut *tx)
+            .await?;
+        self.insert_nodes(&mut tx, nodes).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// List all indexed paths with their chunk count and last indexed time.
+    pub async fn list_paths(&self) -> Result<Vec<IndexedPath>> {
+        let sql = format!(
+            "SELECT path, COUNT(*) AS chunk_count, MAX(COALESCE(updated_at, created_at)) AS last_indexed_at
+            FROM {}
+            GROUP BY path
+            ORDER BY path",
+            self.table_name
+        );
+        let paths = sqlx::query_as(&sql).fetch_all(self.get_pool()).await?;
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{dummy_node, test_store};
+
+    #[tokio::test]
+    #[ignore = "requires a postgres database with the pgvector extension"]
+    async fn replace_and_delete_path_should_work() -> Result<()> {
+        let store = test_store("swiftide_rag_manage").await?;
+
+        let mut nodes = (0..10).map(dummy_node).collect::<Vec<_>>();
+        for node in nodes.iter_mut() {
+            node.path = "src/lib.rs".into();
+        }
+        store.replace_path("src/lib.rs", &nodes).await?;
+
+        // the file shrank to 3 chunks, the other 7 should be gone
+        store.replace_path("src/lib.rs", &nodes[..3]).await?;
+        let paths = store.list_paths().await?;
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].path, "src/lib.rs");
+        assert_eq!(paths[0].chunk_count, 3);
+        assert!(paths[0].last_indexed_at.is_some());
+
+        let deleted = store.delete_path("src/lib.rs").await?;
+        assert_eq!(deleted, 3);
+        assert!(store.list_paths().await?.is_empty());
+
+        let sql = format!("DROP TABLE {}", store.table_name);
+        sqlx::query(&sql).execute(store.get_pool()).await?;
+        Ok(())
+    }
+}
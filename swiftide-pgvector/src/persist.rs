@@ -1,12 +1,15 @@
-use crate::PgVector;
+use std::collections::HashSet;
+
+use crate::{fields::column_name, PgVector};
 use anyhow::Result;
 use async_trait::async_trait;
 use pgvector::Vector;
+use sqlx::{types::Uuid, Postgres, Transaction};
 use swiftide_core::{
     indexing::{EmbeddedField, IndexingStream, Node},
     Persist,
 };
-use tracing::info;
+use tracing::debug;
 
 #[async_trait]
 impl Persist for PgVector {
@@ -24,16 +27,65 @@ impl Persist for PgVector {
                 path VARCHAR NOT NULL,
                 chunk TEXT NOT NULL,
                 metadata JSONB NOT NULL,
-                embedding VECTOR({}),
                 created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
             )",
-            self.table_name, self.vector_size
+            self.table_name
+        );
+        sqlx::query(&sql).execute(&mut *tx).await?;
+
+        for field in &self.fields {
+            let column = column_name(field);
+
+            // create vector column
+            let sql = format!(
+                "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {} VECTOR({})",
+                self.table_name, column, self.vector_size
+            );
+            sqlx::query(&sql).execute(&mut *tx).await?;
+
+            // drop the vector indexes of other settings, the column is searched with one metric
+            for name in
+                self.index_type
+                    .other_index_names(&self.table_name, &column, self.distance_metric)
+            {
+                let sql = format!("DROP INDEX IF EXISTS {}", name);
+                sqlx::query(&sql).execute(&mut *tx).await?;
+            }
+
+            // create vector index
+            let sql = format!(
+                "CREATE INDEX IF NOT EXISTS {} ON {} {}",
+                self.index_type
+                    .index_name(&self.table_name, &column, self.distance_metric),
+                self.table_name,
+                self.index_type.using_clause(&column, self.distance_metric)
+            );
+            sqlx::query(&sql).execute(&mut *tx).await?;
+        }
+
+        // create tsvector column for keyword search
+        let sql = format!(
+            "ALTER TABLE {} ADD COLUMN IF NOT EXISTS chunk_tsv TSVECTOR GENERATED ALWAYS AS (to_tsvector('{}', chunk)) STORED",
+            self.table_name, self.text_search_config
         );
         sqlx::query(&sql).execute(&mut *tx).await?;
 
-        // create hnsw index
+        // create gin index
         let sql = format!(
-            "CREATE INDEX IF NOT EXISTS {}_embedding_idx ON {} USING hnsw (embedding vector_cosine_ops)",
+            "CREATE INDEX IF NOT EXISTS {}_chunk_tsv_idx ON {} USING gin (chunk_tsv)",
+            self.table_name, self.table_name
+        );
+        sqlx::query(&sql).execute(&mut *tx).await?;
+
+        // track when a row was last indexed, and look up rows by path
+        let sql = format!(
+            "ALTER TABLE {} ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP",
+            self.table_name
+        );
+        sqlx::query(&sql).execute(&mut *tx).await?;
+
+        let sql = format!(
+            "CREATE INDEX IF NOT EXISTS {}_path_idx ON {} (path)",
             self.table_name, self.table_name
         );
         sqlx::query(&sql).execute(&mut *tx).await?;
@@ -63,38 +115,147 @@ impl Persist for PgVector {
 }
 
 impl PgVector {
-    async fn store_nodes(&self, nodes: &[Node]) -> Result<()> {
+    pub(crate) async fn store_nodes(&self, nodes: &[Node]) -> Result<()> {
         let pool = self.get_pool();
         let mut tx = pool.begin().await?;
+        self.insert_nodes(&mut tx, nodes).await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
-        for node in nodes {
-            info!("storing node: {:?}", node);
+    pub(crate) async fn insert_nodes(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        nodes: &[Node],
+    ) -> Result<()> {
+        let columns = self.fields.iter().map(column_name).collect::<Vec<_>>();
+        let params = (0..columns.len())
+            .map(|i| format!("${}::vector[]", i + 5))
+            .collect::<Vec<_>>();
+        let sql = format!(
+            "INSERT INTO {table} (id, path, chunk, metadata, {columns})
+            SELECT * FROM UNNEST($1::uuid[], $2::varchar[], $3::text[], $4::jsonb[], {params})
+            ON CONFLICT (id) DO UPDATE SET (path, chunk, metadata, {columns}, updated_at) = (EXCLUDED.path, EXCLUDED.chunk, EXCLUDED.metadata, {excluded}, CURRENT_TIMESTAMP)",
+            table = self.table_name,
+            columns = columns.join(", "),
+            params = params.join(", "),
+            excluded = columns
+                .iter()
+                .map(|c| format!("EXCLUDED.{}", c))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        for batch in nodes.chunks(self.batch_size.max(1)) {
+            let rows = NodeRows::try_from_nodes(batch, &self.fields)?;
+            debug!("storing {} nodes", rows.ids.len());
+
+            let mut query = sqlx::query(&sql)
+                .bind(rows.ids)
+                .bind(rows.paths)
+                .bind(rows.chunks)
+                .bind(rows.metadata);
+            for embeddings in rows.embeddings {
+                query = query.bind(embeddings);
+            }
+            query.execute(&mut **tx).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Column-wise representation of a batch of nodes, bound as arrays to a single `UNNEST` insert.
+#[derive(Debug, Default)]
+struct NodeRows {
+    ids: Vec<Uuid>,
+    paths: Vec<String>,
+    chunks: Vec<String>,
+    metadata: Vec<serde_json::Value>,
+    /// One array per embedded field, in the order of the declared fields.
+    embeddings: Vec<Vec<Vector>>,
+}
+
+impl NodeRows {
+    fn try_from_nodes(nodes: &[Node], fields: &[EmbeddedField]) -> Result<Self> {
+        let mut rows = Self {
+            embeddings: vec![Vec::with_capacity(nodes.len()); fields.len()],
+            ..Default::default()
+        };
+        let mut seen = HashSet::with_capacity(nodes.len());
+
+        // a single upsert can't touch the same row twice, so only the last node for an id is kept
+        for node in nodes.iter().rev() {
             let id = node.id();
-            let path = node.path.to_string_lossy();
-            let chunk = &node.chunk;
-            let metadata = serde_json::to_value(&node.metadata)?;
-            let data = node
-                .vectors
-                .as_ref()
-                .and_then(|v| v.get(&EmbeddedField::Combined))
-                .map(|v| v.iter().map(|f| *f).collect::<Vec<_>>())
-                .unwrap_or_default();
+            if !seen.insert(id) {
+                continue;
+            }
+            for (field, embeddings) in fields.iter().zip(rows.embeddings.iter_mut()) {
+                let Some(data) = node.vectors.as_ref().and_then(|v| v.get(field)) else {
+                    anyhow::bail!(
+                        "Node {} ({}) has no embedding for field {:?}",
+                        id,
+                        node.path.display(),
+                        field
+                    );
+                };
+                embeddings.push(Vector::from(data.clone()));
+            }
 
-            let sql = format!(
-                "INSERT INTO {} (id, path, chunk, metadata, embedding) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (id) DO UPDATE SET (path, chunk, metadata, embedding) = ($2, $3, $4, $5)",
-                self.table_name
-            );
-            sqlx::query(&sql)
-                .bind(id)
-                .bind(path)
-                .bind(chunk)
-                .bind(metadata)
-                .bind(Vector::from(data))
-                .execute(&mut *tx)
-                .await?;
+            rows.ids.push(id);
+            rows.paths.push(node.path.to_string_lossy().into_owned());
+            rows.chunks.push(node.chunk.clone());
+            rows.metadata.push(serde_json::to_value(&node.metadata)?);
         }
 
-        tx.commit().await?;
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::test_utils::{dummy_node, test_store};
+
+    const NODE_COUNT: usize = 5000;
+
+    #[test]
+    fn missing_embedding_should_fail() {
+        let mut node = dummy_node(0);
+        node.vectors = None;
+        let err = NodeRows::try_from_nodes(&[node], &[EmbeddedField::Combined]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("has no embedding for field Combined"));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a postgres database with the pgvector extension"]
+    async fn store_nodes_should_handle_thousands_of_nodes() -> Result<()> {
+        let store = test_store("swiftide_rag_bench").await?;
+
+        let nodes = (0..NODE_COUNT).map(dummy_node).collect::<Vec<_>>();
+
+        let start = Instant::now();
+        store.store_nodes(&nodes).await?;
+        let elapsed = start.elapsed();
+        println!(
+            "stored {} nodes in {:?} ({:.0} nodes/s)",
+            NODE_COUNT,
+            elapsed,
+            NODE_COUNT as f64 / elapsed.as_secs_f64()
+        );
+
+        // storing the same nodes again should upsert instead of duplicating
+        store.store_nodes(&nodes).await?;
+        let sql = format!("SELECT COUNT(*) FROM {}", store.table_name);
+        let (count,): (i64,) = sqlx::query_as(&sql).fetch_one(store.get_pool()).await?;
+        assert_eq!(count, NODE_COUNT as i64);
+
+        let sql = format!("DROP TABLE {}", store.table_name);
+        sqlx::query(&sql).execute(store.get_pool()).await?;
         Ok(())
     }
 }
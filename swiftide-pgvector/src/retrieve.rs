@@ -8,15 +8,39 @@ use swiftide_core::{
 };
 use tracing::info;
 
-use crate::PgVector;
+use crate::{fields::column_name, HybridKeywordSearch, PgVector};
 
-const DEFAULT_LIMIT: usize = 5;
+/// A retrieved chunk and the node it was stored from, e.g. to cite its source.
+#[derive(Debug, Clone, Default, FromRow, PartialEq, Eq)]
+pub struct RetrievedChunk {
+    pub id: Uuid,
+    pub path: String,
+    pub chunk: String,
+}
 
-#[allow(dead_code)]
-#[derive(Debug, Clone, FromRow)]
-struct RetrievalResult {
-    id: Uuid,
-    chunk: String,
+impl PgVector {
+    /// The `top_k` chunks closest to `embedding` on the search field.
+    #[tracing::instrument(skip(self, embedding))]
+    pub async fn search_similar(
+        &self,
+        embedding: &[f32],
+        top_k: u64,
+    ) -> Result<Vec<RetrievedChunk>> {
+        let sql = format!(
+            "SELECT id, path, chunk FROM {} ORDER BY {} {} $1 LIMIT $2",
+            self.table_name,
+            column_name(&self.search_field),
+            self.distance_metric.operator()
+        );
+
+        info!("running query: {}", sql);
+        let data = sqlx::query_as(&sql)
+            .bind(Vector::from(embedding.to_vec()))
+            .bind(top_k as i64)
+            .fetch_all(self.get_pool())
+            .await?;
+        Ok(data)
+    }
 }
 
 #[async_trait]
@@ -31,20 +55,8 @@ impl Retrieve<SimilaritySingleEmbedding<String>> for PgVector {
             anyhow::bail!("No embedding for query")
         };
 
-        let embedding = Vector::from(embedding.clone());
-
-        let pool = self.get_pool();
-
-        let sql = format!(
-            "SELECT id, chunk FROM {} ORDER BY embedding <=> $1 LIMIT $2",
-            self.table_name
-        );
-
-        info!("running query: {}", sql);
-        let data: Vec<RetrievalResult> = sqlx::query_as(&sql)
-            .bind(embedding)
-            .bind(DEFAULT_LIMIT as i32)
-            .fetch_all(pool)
+        let data = self
+            .search_similar(embedding, search_strategy.top_k())
             .await?;
 
         let docs = data.into_iter().map(|r| r.chunk).collect();
@@ -67,3 +79,103 @@ impl Retrieve<SimilaritySingleEmbedding> for PgVector {
         .await
     }
 }
+
+#[async_trait]
+impl Retrieve<HybridKeywordSearch> for PgVector {
+    #[tracing::instrument]
+    async fn retrieve(
+        &self,
+        search_strategy: &HybridKeywordSearch,
+        query: Query<states::Pending>,
+    ) -> Result<Query<states::Retrieved>> {
+        let Some(embedding) = &query.embedding else {
+            anyhow::bail!("No embedding for query")
+        };
+
+        let embedding = Vector::from(embedding.clone());
+
+        let pool = self.get_pool();
+
+        // reciprocal rank fusion of the vector and the keyword ranking
+        let sql = format!(
+            "WITH semantic AS (
+                SELECT id, RANK() OVER (ORDER BY {column} {op} $1) AS rank
+                FROM {table}
+                ORDER BY {column} {op} $1
+                LIMIT $3
+            ),
+            keyword AS (
+                SELECT id, RANK() OVER (ORDER BY ts_rank_cd(chunk_tsv, q) DESC) AS rank
+                FROM {table}, websearch_to_tsquery('{config}', $2) q
+                WHERE chunk_tsv @@ q
+                ORDER BY ts_rank_cd(chunk_tsv, q) DESC
+                LIMIT $3
+            )
+            SELECT t.id, t.path, t.chunk
+            FROM semantic
+            FULL OUTER JOIN keyword ON semantic.id = keyword.id
+            JOIN {table} t ON t.id = COALESCE(semantic.id, keyword.id)
+            ORDER BY COALESCE(1.0 / ($4 + semantic.rank), 0.0) + COALESCE(1.0 / ($4 + keyword.rank), 0.0) DESC
+            LIMIT $5",
+            table = self.table_name,
+            config = self.text_search_config,
+            column = column_name(&self.search_field),
+            op = self.distance_metric.operator()
+        );
+
+        info!("running query: {}", sql);
+        let data: Vec<RetrievedChunk> = sqlx::query_as(&sql)
+            .bind(embedding)
+            .bind(query.current())
+            .bind(search_strategy.candidates() as i64)
+            .bind(search_strategy.rrf_k() as i64)
+            .bind(search_strategy.top_k() as i64)
+            .fetch_all(pool)
+            .await?;
+
+        let docs = data.into_iter().map(|r| r.chunk).collect();
+        Ok(query.retrieved_documents(docs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use swiftide_core::indexing::EmbeddedField;
+
+    use super::*;
+    use crate::test_utils::{dummy_node, test_store};
+
+    #[tokio::test]
+    #[ignore = "requires a postgres database with the pgvector extension"]
+    async fn hybrid_search_should_fuse_keyword_and_vector_ranks() -> Result<()> {
+        let store = test_store("swiftide_rag_hybrid").await?;
+
+        let mut nodes = (0..10).map(dummy_node).collect::<Vec<_>>();
+        for (i, node) in nodes.iter_mut().enumerate() {
+            node.chunk = format!("notes about topic {}", i);
+        }
+        nodes[7].chunk = "the zebra crossed the road".to_string();
+        store.store_nodes(&nodes).await?;
+
+        // closest to node 0 by vector, only node 7 matches the keyword
+        let mut query = Query::<states::Pending>::new("zebra");
+        query.embedding = nodes[0]
+            .vectors
+            .as_ref()
+            .and_then(|v| v.get(&EmbeddedField::Combined))
+            .cloned();
+        let mut strategy = HybridKeywordSearch::default();
+        strategy.with_top_k(2);
+        let query = store.retrieve(&strategy, query).await?;
+
+        // node 7 ranks on both sides, node 0 only on the vector side
+        assert_eq!(
+            query.documents(),
+            &[nodes[7].chunk.clone(), nodes[0].chunk.clone()]
+        );
+
+        let sql = format!("DROP TABLE {}", store.table_name);
+        sqlx::query(&sql).execute(store.get_pool()).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use swiftide_core::querying::SearchStrategy;
+
+const DEFAULT_TOP_K: u64 = 5;
+const DEFAULT_CANDIDATES: u64 = 50;
+const DEFAULT_RRF_K: u64 = 60;
+
+/// A search strategy that combines full-text ranking on the chunk with cosine
+/// distance on the embedding, merged with reciprocal rank fusion.
+///
+/// Each side fetches up to `candidates` rows, and a document scores
+/// `1 / (rrf_k + rank)` for every side it appears in.
+#[derive(Debug, Clone)]
+pub struct HybridKeywordSearch {
+    top_k: u64,
+    candidates: u64,
+    rrf_k: u64,
+}
+
+impl Default for HybridKeywordSearch {
+    fn default() -> Self {
+        Self {
+            top_k: DEFAULT_TOP_K,
+            candidates: DEFAULT_CANDIDATES,
+            rrf_k: DEFAULT_RRF_K,
+        }
+    }
+}
+
+impl SearchStrategy for HybridKeywordSearch {}
+
+impl HybridKeywordSearch {
+    /// Number of documents to return after fusion.
+    pub fn with_top_k(&mut self, top_k: u64) -> &mut Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Number of candidates fetched by the keyword and the vector search each.
+    pub fn with_candidates(&mut self, candidates: u64) -> &mut Self {
+        self.candidates = candidates;
+        self
+    }
+
+    /// The `k` constant of reciprocal rank fusion, higher values flatten the ranks.
+    pub fn with_rrf_k(&mut self, rrf_k: u64) -> &mut Self {
+        self.rrf_k = rrf_k;
+        self
+    }
+
+    pub fn top_k(&self) -> u64 {
+        self.top_k
+    }
+
+    pub fn candidates(&self) -> u64 {
+        self.candidates
+    }
+
+    pub fn rrf_k(&self) -> u64 {
+        self.rrf_k
+    }
+}